@@ -0,0 +1,152 @@
+/// AliveRanges records which frames a component was alive for, within the same window of
+/// frames that a `FrameBuffer` holds values for.
+///
+/// Each buffered frame stores whether the component was alive, so lookups don't depend on how
+/// often it was added and removed. Frames newer than the buffer share the state of the newest
+/// one, and frames that have dropped out of the window are forgotten.
+///
+use crate::prelude::*;
+use bevy::prelude::*;
+use std::fmt;
+
+#[derive(Clone)]
+pub struct AliveRanges {
+    frames: FrameBuffer<bool>,
+}
+
+impl fmt::Debug for AliveRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AliveRanges{:?}", self.ranges())
+    }
+}
+
+impl AliveRanges {
+    pub fn with_capacity(len: usize) -> Self {
+        Self {
+            frames: FrameBuffer::with_capacity(len, "AliveRanges"),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.frames.capacity()
+    }
+
+    /// change how many frames are remembered, in place.
+    pub fn set_capacity(&mut self, len: usize) {
+        self.frames.set_capacity(len);
+    }
+
+    /// Smallest frame we know about. Frames before this are reported as not alive.
+    pub fn oldest_frame(&self) -> FrameNumber {
+        self.frames.oldest_frame()
+    }
+
+    pub fn alive_at_frame(&self, frame: FrameNumber) -> bool {
+        let frame = frame.min(self.frames.newest_frame());
+        self.frames.get(frame).copied().unwrap_or(false)
+    }
+
+    /// The component is alive (or not) from `frame` onwards.
+    /// Replaces anything recorded for later frames.
+    pub fn set_alive(&mut self, frame: FrameNumber, alive: bool) -> Result<(), TimewarpError> {
+        let newest = self.frames.newest_frame();
+        if self.frames.get(newest).is_some() && frame <= newest {
+            if frame < self.frames.oldest_frame() {
+                return Err(TimewarpError::FrameTooOld);
+            }
+            for f in frame..=newest {
+                self.frames.insert(f, alive)?;
+            }
+            return Ok(());
+        }
+        if self.frames.get(newest).is_some() {
+            // frames between the newest one and this are unchanged
+            let before = self.alive_at_frame(newest);
+            let window_start = frame.saturating_sub(self.frames.capacity() as FrameNumber);
+            for f in (newest + 1).max(window_start)..frame {
+                self.frames.insert(f, before)?;
+            }
+        }
+        self.frames.insert(frame, alive)
+    }
+
+    /// Forgets changes after `frame`, so later frames have the same state as it.
+    pub fn forget_after(&mut self, frame: FrameNumber) {
+        if frame < self.frames.oldest_frame() {
+            debug!("Can't forget alive ranges after {frame}, it's older than the window");
+            return;
+        }
+        self.frames.remove_entries_newer_than(frame);
+    }
+
+    /// Forgets frames before `frame`. Frames after the newest one we know about are unaffected.
+    pub fn forget_before(&mut self, frame: FrameNumber) {
+        self.frames.remove_entries_older_than(frame);
+    }
+
+    /// The alive ranges in the window, as (start, end) with an exclusive end.
+    /// The last one is open-ended (None) if the component is still alive.
+    pub fn ranges(&self) -> Vec<FrameRange> {
+        let mut ranges: Vec<FrameRange> = Vec::new();
+        if self.frames.get(self.frames.newest_frame()).is_none() {
+            return ranges;
+        }
+        for frame in self.frames.oldest_frame()..=self.frames.newest_frame() {
+            let alive = self.alive_at_frame(frame);
+            let open = ranges.last().is_some_and(|(_, end)| end.is_none());
+            match (alive, open) {
+                (true, false) => ranges.push((frame, None)),
+                (false, true) => ranges.last_mut().unwrap().1 = Some(frame),
+                _ => {}
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alive_ranges() {
+        let mut ar = AliveRanges::with_capacity(5);
+        assert!(!ar.alive_at_frame(1));
+        ar.set_alive(2, true).unwrap();
+        assert!(!ar.alive_at_frame(1));
+        assert!(ar.alive_at_frame(2));
+        assert!(ar.alive_at_frame(100));
+        ar.set_alive(4, false).unwrap();
+        ar.set_alive(6, true).unwrap();
+        assert_eq!(ar.ranges(), vec![(2, Some(4)), (6, None)]);
+        assert!(ar.alive_at_frame(3));
+        assert!(!ar.alive_at_frame(5));
+
+        // replaces later changes
+        ar.set_alive(3, false).unwrap();
+        assert_eq!(ar.ranges(), vec![(2, Some(3))]);
+
+        // old frames drop out of the window
+        ar.set_alive(20, true).unwrap();
+        assert_eq!(ar.ranges(), vec![(20, None)]);
+        assert_eq!(ar.oldest_frame(), 16);
+        assert!(matches!(
+            ar.set_alive(10, false),
+            Err(TimewarpError::FrameTooOld)
+        ));
+    }
+
+    #[test]
+    fn test_forget_after() {
+        let mut ar = AliveRanges::with_capacity(10);
+        ar.set_alive(1, true).unwrap();
+        ar.set_alive(3, false).unwrap();
+        ar.set_alive(5, true).unwrap();
+        ar.set_alive(7, false).unwrap();
+        ar.forget_after(5);
+        assert_eq!(ar.ranges(), vec![(1, Some(3)), (5, None)]);
+        ar.forget_after(4);
+        assert_eq!(ar.ranges(), vec![(1, Some(3))]);
+        assert!(!ar.alive_at_frame(8));
+    }
+}
@@ -1,11 +1,34 @@
-use crate::{prelude::TimewarpError, FrameBuffer, FrameNumber, TimewarpComponent};
+use crate::{prelude::TimewarpError, AliveRanges, FrameBuffer, FrameNumber, TimewarpComponent};
 use bevy::prelude::*;
+use std::marker::PhantomData;
 
 /// entities with NoRollback are ignored, even if they have components which
 /// have been registered for rollback.
 #[derive(Component)]
 pub struct NoRollback;
 
+/// Groups entities that can interact with eachother, for partial rollbacks.
+///
+/// When `TimewarpConfig::partial_rollback` is enabled, a rollback only restores and resimulates
+/// entities in the same islands as the entities that triggered it. Entities without a
+/// `RollbackIsland` are always included.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RollbackIsland(pub u32);
+
+/// Added to entities that aren't part of the current partial rollback, and removed once the
+/// rollback completes. They keep their current values, so exclude them from your game systems
+/// with `Without<SkipResimulation>`.
+#[derive(Component, Debug)]
+pub struct SkipResimulation;
+
+/// Mirrors bevy's `Parent` for timewarp entities, once you've called
+/// `app.register_rollback_hierarchy()`. This is what gets recorded in a ComponentHistory, and
+/// rolling it back reattaches or detaches the entity from its parent.
+///
+/// You don't need to insert this yourself, just use the normal hierarchy commands.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimewarpParent(pub Entity);
+
 /// Added to every entity for metrics
 #[derive(Component, Debug)]
 pub struct TimewarpStatus {
@@ -43,6 +66,7 @@ impl TimewarpStatus {
 
 /// Used when you want to insert a component T, but for an older frame.
 /// insert this to an entity for an older frame will trigger a rollback.
+/// for a future frame, it's held until the clock reaches that frame, then applied.
 ///
 /// Note: this is for timewarp-registered components.
 ///
@@ -93,6 +117,25 @@ pub struct TimewarpCorrection<T: TimewarpComponent> {
     pub frame: FrameNumber,
 }
 
+/// Resimulating writes to every component, so `Changed<T>` fires for everything after a rollback.
+/// This is added to (or updated on) an entity when a rollback completes, only if the value of `T`
+/// differs from what it was before the rollback. Query `Changed<ChangedAfterRollback<T>>` to
+/// find components that a rollback actually changed.
+#[derive(Component, Debug)]
+pub struct ChangedAfterRollback<T: TimewarpComponent> {
+    /// the last frame of the rollback that changed it
+    pub frame: FrameNumber,
+    _component: PhantomData<T>,
+}
+impl<T: TimewarpComponent> ChangedAfterRollback<T> {
+    pub fn new(frame: FrameNumber) -> Self {
+        Self {
+            frame,
+            _component: PhantomData,
+        }
+    }
+}
+
 /// Buffers the last few authoritative component values received from the server
 #[derive(Component)]
 pub struct ServerSnapshot<T: TimewarpComponent> {
@@ -121,17 +164,38 @@ impl<T: TimewarpComponent> ServerSnapshot<T> {
             Some(nf)
         }
     }
+    /// The last confirmed value: the newest authoritative value at or before `frame`,
+    /// and the frame it was for. Anything we have after that frame is just our prediction.
+    pub fn confirmed_at_or_before(&self, frame: FrameNumber) -> Option<(FrameNumber, &T)> {
+        let newest = frame.min(self.values.newest_frame());
+        (self.values.oldest_frame()..=newest)
+            .rev()
+            .find_map(|f| self.values.get(f).map(|val| (f, val)))
+    }
 }
 
 /// used to record component birth/death ranges in ComponentHistory.
 /// (start, end) – can be open-ended if end is None.
 pub type FrameRange = (FrameNumber, Option<FrameNumber>);
 
+/// A component inserted or removed between frames, outside of the game simulation.
+/// (eg. by networking code)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalChange<T> {
+    Inserted(T),
+    Removed,
+}
+
 /// Buffers component values for the last few frames.
 #[derive(Component)]
 pub struct ComponentHistory<T: TimewarpComponent> {
-    pub values: FrameBuffer<T>,        // not pub!
-    pub alive_ranges: Vec<FrameRange>, // inclusive! unlike std:range
+    pub values: FrameBuffer<T>, // not pub!
+    pub alive_ranges: AliveRanges,
+    /// resimulating won't recreate these, so they are replayed during rollback.
+    /// keyed by the first frame simulated after the change.
+    pub external_changes: FrameBuffer<ExternalChange<T>>,
+    /// was T on the entity when the last simulated frame was recorded
+    pub(crate) present: bool,
     pub correction_logging_enabled: bool,
 }
 
@@ -145,9 +209,14 @@ impl<T: TimewarpComponent> ComponentHistory<T> {
         component: T,
         entity: &Entity,
     ) -> Self {
+        let mut alive_ranges = AliveRanges::with_capacity(len);
+        // can't error on a brand new buffer:
+        _ = alive_ranges.set_alive(birth_frame, true);
         let mut this = Self {
             values: FrameBuffer::with_capacity(len, "CH"),
-            alive_ranges: vec![(birth_frame, None)],
+            alive_ranges,
+            external_changes: FrameBuffer::with_capacity(len, "CH.external"),
+            present: false,
             correction_logging_enabled: false,
         };
         trace!("CH.new {entity:?} {birth_frame} = {component:?}");
@@ -188,13 +257,7 @@ impl<T: TimewarpComponent> ComponentHistory<T> {
             .remove_entries_newer_than(frame.saturating_sub(1));
     }
     pub fn alive_at_frame(&self, frame: FrameNumber) -> bool {
-        // self.values.get(frame).is_some()
-        for (start, maybe_end) in &self.alive_ranges {
-            if *start <= frame && (maybe_end.is_none() || maybe_end.unwrap() > frame) {
-                return true;
-            }
-        }
-        false
+        self.alive_ranges.alive_at_frame(frame)
     }
     pub fn report_birth_at_frame(&mut self, frame: FrameNumber) {
         trace!("component birth @ {frame} {:?}", std::any::type_name::<T>());
@@ -206,33 +269,49 @@ impl<T: TimewarpComponent> ComponentHistory<T> {
             self.values.get(frame).is_some(),
             "No stored component value when reporting birth @ {frame}"
         );
-        if self.alive_ranges.last().unwrap().1 == Some(frame) {
-            return;
+        // (if it was removed and reinserted during the same frame, it never really died)
+        if let Err(err) = self.alive_ranges.set_alive(frame, true) {
+            warn!("{err:?} reporting birth @ {frame} {}", self.type_name());
         }
-        self.alive_ranges.push((frame, None));
     }
+    /// The component isn't alive from `frame` onwards.
+    ///
+    /// Later frames are marked dead too, overwriting any births left over from before a rollback.
+    /// Stored values aren't removed, they just aren't alive.
     pub fn report_death_at_frame(&mut self, frame: FrameNumber) {
-        // currently after rollback we get (harmless?) erroneous RemovedComponent<> reports
-        // so we just supress here for now.
-        //
-        // need to consider whether it's worth wiping alive_ranges on rolling back,
-        // and having them repopulate during fast-fwd.
         if !self.alive_at_frame(frame) {
+            trace!("Can't report death of component not alive @ {frame}");
             return;
         }
-
-        assert!(
-            self.alive_at_frame(frame),
-            "Can't report death of component not alive"
-        );
-        if self.alive_ranges.last().unwrap().1 == Some(frame) {
-            return;
+        if let Err(err) = self.alive_ranges.set_alive(frame, false) {
+            warn!("{err:?} reporting death @ {frame} {}", self.type_name());
         }
-        self.alive_ranges.last_mut().unwrap().1 = Some(frame);
         trace!(
             "component death @ {frame} {:?} --> {:?}",
             std::any::type_name::<T>(),
             self.alive_ranges
         );
     }
+    /// Records a component inserted or removed between frames, before `frame` was simulated.
+    pub fn report_external_change(&mut self, frame: FrameNumber, change: ExternalChange<T>) {
+        trace!(
+            "external change @ {frame} {:?} {change:?}",
+            std::any::type_name::<T>()
+        );
+        if let Err(err) = self.external_changes.insert(frame, change) {
+            warn!("{err:?} recording external change @ {frame}");
+        }
+    }
+    /// Forgets births and deaths after `frame`, when rolling back to it.
+    /// They are recorded again as the following frames are resimulated.
+    pub fn forget_lifecycle_after(&mut self, frame: FrameNumber) {
+        self.alive_ranges.forget_after(frame);
+    }
+    /// Forgets values, births, deaths and external changes before `frame`,
+    /// eg. once it can never be rolled back to.
+    pub fn forget_before(&mut self, frame: FrameNumber) {
+        self.values.remove_entries_older_than(frame);
+        self.alive_ranges.forget_before(frame);
+        self.external_changes.remove_entries_older_than(frame);
+    }
 }
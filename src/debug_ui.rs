@@ -0,0 +1,300 @@
+//! Optional debug inspector, enable the `debug_ui` cargo feature.
+//!
+//! Adding the [`TimewarpDebugPlugin`] collects the state of every registered component into a
+//! [`TimewarpDebugInfo`] resource each frame, along with a timeline of recent rollbacks.
+//! It doesn't draw anything itself, so it can be inspected headless (eg. in tests), or shown
+//! in whatever UI your game uses. Its `Display` impl renders a plain text panel:
+//!
+//! ```rust,ignore
+//! app.add_plugins(TimewarpDebugPlugin::default());
+//!
+//! fn show_debug_panel(info: Res<TimewarpDebugInfo>, mut q: Query<&mut Text, With<DebugPanel>>) {
+//!     q.single_mut().sections[0].value = info.to_string();
+//! }
+//! ```
+use crate::prelude::*;
+use bevy::{prelude::*, utils::Duration};
+use std::{collections::VecDeque, fmt, marker::PhantomData, ops::Range};
+
+/// Collects [`TimewarpDebugInfo`] each frame.
+pub struct TimewarpDebugPlugin<F: FrameSource = GameClock> {
+    /// how many completed rollbacks and corrections to remember
+    pub history_len: usize,
+    _frame_source: PhantomData<F>,
+}
+
+impl Default for TimewarpDebugPlugin {
+    fn default() -> Self {
+        Self::with_frame_source(20)
+    }
+}
+
+impl<F: FrameSource> TimewarpDebugPlugin<F> {
+    /// For apps using `TimewarpPlugin::<F>::with_frame_source`.
+    pub fn with_frame_source(history_len: usize) -> Self {
+        Self {
+            history_len,
+            _frame_source: PhantomData,
+        }
+    }
+}
+
+impl<F: FrameSource> Plugin for TimewarpDebugPlugin<F> {
+    fn build(&self, app: &mut App) {
+        let schedule = app
+            .world
+            .get_resource::<TimewarpConfig>()
+            .expect("TimewarpPlugin must be added before TimewarpDebugPlugin")
+            .schedule();
+        app.insert_resource(TimewarpDebugInfo::with_history_len(self.history_len));
+        app.add_systems(
+            schedule,
+            (
+                collect_rollback_timeline,
+                begin_debug_frame::<F>.run_if(not_in_rollback()),
+            )
+                .chain()
+                .in_set(TimewarpPostfixSet::Last),
+        );
+    }
+}
+
+/// What the debug inspector knows about one registered component of an entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentDebugInfo {
+    pub type_name: &'static str,
+    /// which of the buffered frames in the `ComponentHistory` have values, newest first
+    pub occupancy: Vec<bool>,
+    /// the frame the first `occupancy` entry is for
+    pub newest_frame: FrameNumber,
+    pub alive_ranges: Vec<FrameRange>,
+    /// frames we have server snapshots for, oldest first
+    pub snapshot_frames: Vec<FrameNumber>,
+}
+
+/// What the debug inspector knows about one entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityDebugInfo {
+    pub entity: Entity,
+    /// how many rollbacks updates to this entity have requested
+    pub rollback_triggers: u32,
+    pub last_snapshot_frame: FrameNumber,
+    pub components: Vec<ComponentDebugInfo>,
+}
+
+/// A completed rollback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollbackTimelineEntry {
+    pub range: Range<FrameNumber>,
+    pub depth: FrameNumber,
+    pub triggered_by: Vec<Entity>,
+    pub component_types: Vec<&'static str>,
+    pub duration: Duration,
+}
+
+/// A misprediction, from a `TimewarpCorrection`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrectionDebugInfo {
+    pub entity: Entity,
+    pub type_name: &'static str,
+    pub frame: FrameNumber,
+}
+
+/// Everything the debug inspector shows, rebuilt each frame we aren't rolling back.
+#[derive(Resource, Debug)]
+pub struct TimewarpDebugInfo {
+    pub frame: FrameNumber,
+    pub stats: RollbackStats,
+    /// sorted by entity
+    pub entities: Vec<EntityDebugInfo>,
+    /// most recent last
+    pub rollbacks: VecDeque<RollbackTimelineEntry>,
+    /// most recent last
+    pub corrections: VecDeque<CorrectionDebugInfo>,
+    history_len: usize,
+}
+
+impl TimewarpDebugInfo {
+    pub fn with_history_len(history_len: usize) -> Self {
+        Self {
+            frame: 0,
+            stats: RollbackStats::default(),
+            entities: Vec::new(),
+            rollbacks: VecDeque::with_capacity(history_len),
+            corrections: VecDeque::with_capacity(history_len),
+            history_len,
+        }
+    }
+    pub fn entity(&self, entity: Entity) -> Option<&EntityDebugInfo> {
+        self.entities
+            .binary_search_by_key(&entity, |info| info.entity)
+            .ok()
+            .map(|index| &self.entities[index])
+    }
+    fn entity_mut(
+        &mut self,
+        entity: Entity,
+        status: Option<&TimewarpStatus>,
+    ) -> &mut EntityDebugInfo {
+        let index = match self
+            .entities
+            .binary_search_by_key(&entity, |info| info.entity)
+        {
+            Ok(index) => index,
+            Err(index) => {
+                self.entities.insert(
+                    index,
+                    EntityDebugInfo {
+                        entity,
+                        rollback_triggers: status.map_or(0, |s| s.rollback_triggers()),
+                        last_snapshot_frame: status.map_or(0, |s| s.last_snap_frame()),
+                        components: Vec::new(),
+                    },
+                );
+                index
+            }
+        };
+        &mut self.entities[index]
+    }
+    fn push_rollback(&mut self, entry: RollbackTimelineEntry) {
+        if self.rollbacks.len() == self.history_len {
+            self.rollbacks.pop_front();
+        }
+        self.rollbacks.push_back(entry);
+    }
+    fn push_correction(&mut self, correction: CorrectionDebugInfo) {
+        if self.corrections.len() == self.history_len {
+            self.corrections.pop_front();
+        }
+        self.corrections.push_back(correction);
+    }
+}
+
+fn short_type_name(type_name: &str) -> &str {
+    type_name.rsplit("::").next().unwrap_or(type_name)
+}
+
+impl fmt::Display for TimewarpDebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "frame {} | rollbacks {} | range faults {} | overflows {} | non-rollback updates {}",
+            self.frame,
+            self.stats.num_rollbacks,
+            self.stats.range_faults,
+            self.stats.rollback_overflows,
+            self.stats.non_rollback_updates,
+        )?;
+        writeln!(f, "recent rollbacks:")?;
+        for rb in self.rollbacks.iter().rev() {
+            let types = rb
+                .component_types
+                .iter()
+                .map(|t| short_type_name(t))
+                .collect::<Vec<_>>();
+            writeln!(
+                f,
+                "  {}..={} depth {} by {:?} {types:?} {:?}",
+                rb.range.start, rb.range.end, rb.depth, rb.triggered_by, rb.duration
+            )?;
+        }
+        writeln!(f, "recent corrections:")?;
+        for c in self.corrections.iter().rev() {
+            writeln!(
+                f,
+                "  {:?} {} @ {}",
+                c.entity,
+                short_type_name(c.type_name),
+                c.frame
+            )?;
+        }
+        writeln!(f, "entities:")?;
+        for e in self.entities.iter() {
+            writeln!(
+                f,
+                "  {:?} triggers {} last snapshot {}",
+                e.entity, e.rollback_triggers, e.last_snapshot_frame
+            )?;
+            for c in e.components.iter() {
+                let occupancy = c
+                    .occupancy
+                    .iter()
+                    .map(|o| if *o { '#' } else { '.' })
+                    .collect::<String>();
+                writeln!(
+                    f,
+                    "    {} [{occupancy}] @ {} alive {:?} snapshots {:?}",
+                    short_type_name(c.type_name),
+                    c.newest_frame,
+                    c.alive_ranges,
+                    c.snapshot_frames
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Clears per-entity info, so entities that went away aren't shown.
+/// Runs before the per-component systems fill it in again.
+pub(crate) fn begin_debug_frame<F: FrameSource>(
+    mut info: ResMut<TimewarpDebugInfo>,
+    game_clock: Res<F>,
+    stats: Res<RollbackStats>,
+) {
+    info.frame = game_clock.frame();
+    info.stats = stats.clone();
+    info.entities.clear();
+}
+
+/// Adds completed rollbacks to the timeline.
+pub(crate) fn collect_rollback_timeline(
+    mut info: ResMut<TimewarpDebugInfo>,
+    mut completed: EventReader<RollbackCompleted>,
+) {
+    for ev in completed.iter() {
+        info.push_rollback(RollbackTimelineEntry {
+            range: ev.range.clone(),
+            depth: ev.depth,
+            triggered_by: ev.triggered_by.clone(),
+            component_types: ev.component_types.clone(),
+            duration: ev.duration,
+        });
+    }
+}
+
+/// Per-component part of the debug info.
+pub(crate) fn collect_component_debug_info<T: TimewarpComponent>(
+    mut info: ResMut<TimewarpDebugInfo>,
+    q: Query<(
+        Entity,
+        &ComponentHistory<T>,
+        &ServerSnapshot<T>,
+        Option<&TimewarpStatus>,
+    )>,
+    q_corrections: Query<(Entity, &TimewarpCorrection<T>), Changed<TimewarpCorrection<T>>>,
+) {
+    for (entity, ch, ss, opt_status) in q.iter() {
+        let snapshot_frames = ss
+            .values
+            .current_range()
+            .filter(|frame| ss.at_frame(*frame).is_some())
+            .collect();
+        info.entity_mut(entity, opt_status)
+            .components
+            .push(ComponentDebugInfo {
+                type_name: std::any::type_name::<T>(),
+                occupancy: ch.values.frame_occupancy(),
+                newest_frame: ch.values.newest_frame(),
+                alive_ranges: ch.alive_ranges.ranges(),
+                snapshot_frames,
+            });
+    }
+    for (entity, correction) in q_corrections.iter() {
+        info.push_correction(CorrectionDebugInfo {
+            entity,
+            type_name: std::any::type_name::<T>(),
+            frame: correction.frame,
+        });
+    }
+}
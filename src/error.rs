@@ -5,6 +5,17 @@
 pub enum TimewarpError {
     Io(std::io::Error),
     FrameTooOld,
+    EntityMissing(bevy::prelude::Entity),
+    /// a delta's baseline value isn't in the entity's `ServerSnapshot`
+    MissingBaseline {
+        entity: bevy::prelude::Entity,
+        frame: crate::prelude::FrameNumber,
+    },
+    /// a delta for one frame was added to a batch for another
+    FrameMismatch {
+        expected: crate::prelude::FrameNumber,
+        got: crate::prelude::FrameNumber,
+    },
 }
 
 impl std::fmt::Display for TimewarpError {
@@ -53,6 +53,18 @@ where
         }
     }
 
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// change how many frames are buffered, in place.
+    /// shrinking drops the oldest values, growing keeps everything we have and buffers more
+    /// frames from now on (we can't recover values that were already dropped).
+    pub fn set_capacity(&mut self, len: usize) {
+        self.capacity = len;
+        self.entries.truncate(len);
+    }
+
     pub fn current_range(&self) -> Range<FrameNumber> {
         Range {
             start: self.oldest_frame(),
@@ -86,6 +98,13 @@ where
         }
     }
 
+    /// removes entries for frames smaller than `frame`
+    pub fn remove_entries_older_than(&mut self, frame: FrameNumber) {
+        if let Some(index) = self.index(frame) {
+            self.entries.truncate(index + 1);
+        }
+    }
+
     /// value at frame, or None if out of the range of values currently stored in the buffer
     /// by design, at the moment, we don't distinguish between returning a stored None value,
     /// and returning None because the requested frame is out of range. (because we don't care)
@@ -128,6 +147,11 @@ where
         }
     }
 
+    /// all the buffered values, newest first.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.entries.iter_mut().flatten()
+    }
+
     // which frames have values?
     pub fn frame_occupancy(&self) -> Vec<bool> {
         self.entries.iter().map(|e| e.is_some()).collect::<Vec<_>>()
@@ -278,4 +302,29 @@ mod tests {
         assert_eq!(fb.get(4), Some(&4));
         assert_eq!(fb.get(3), None);
     }
+
+    #[test]
+    fn test_set_capacity() {
+        let mut fb = FrameBuffer::<u32>::with_capacity(5, "");
+        for f in 1..=5 {
+            fb.insert(f, f).unwrap();
+        }
+        fb.set_capacity(3);
+        assert_eq!(fb.capacity(), 3);
+        assert_eq!(fb.oldest_frame(), 3);
+        assert_eq!(fb.get(2), None);
+        assert_eq!(fb.get(3), Some(&3));
+        assert!(matches!(fb.insert(2, 2), Err(TimewarpError::FrameTooOld)));
+
+        fb.set_capacity(6);
+        assert_eq!(fb.get(3), Some(&3));
+        for f in 6..=8 {
+            fb.insert(f, f).unwrap();
+        }
+        // grew to hold 6 frames
+        assert_eq!(fb.oldest_frame(), 3);
+        fb.insert(9, 9).unwrap();
+        assert_eq!(fb.oldest_frame(), 4);
+        assert_eq!(fb.get(4), Some(&4));
+    }
 }
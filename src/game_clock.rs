@@ -3,6 +3,7 @@ use bevy::prelude::*;
 use std::fmt;
 use std::ops::Deref;
 
+/// The frame number, advanced by timewarp before your game logic runs each frame.
 #[derive(Resource, Default)]
 pub struct GameClock {
     pub frames_ahead: i8,
@@ -28,6 +29,58 @@ impl GameClock {
     }
 }
 
+/// A resource holding the frame number, that timewarp can read and rewind.
+///
+/// Implement this for your own tick resource, add the plugin with
+/// `TimewarpPlugin::<MyTick>::with_frame_source(config)`, and register components via
+/// `TimewarpApp::<MyTick>::new(&mut app)`. Timewarp then reads and rewinds your resource
+/// directly, and doesn't insert a [`GameClock`].
+/// Timewarp advances it each frame in [`TimewarpPrefixSet::AdvanceFrame`], so don't advance it
+/// yourself.
+pub trait FrameSource: Resource + fmt::Debug {
+    fn frame(&self) -> FrameNumber;
+    fn set_frame(&mut self, frame: FrameNumber);
+    fn advance(&mut self, frames: FrameNumber) {
+        let frame = self.frame();
+        self.set_frame(frame + frames);
+    }
+}
+
+impl FrameSource for GameClock {
+    fn frame(&self) -> FrameNumber {
+        self.frame
+    }
+    fn set_frame(&mut self, frame: FrameNumber) {
+        self.frame = frame;
+    }
+    fn advance(&mut self, frames: FrameNumber) {
+        self.frame += frames;
+    }
+}
+
+/// Advances to the frame about to be simulated, before your game logic runs.
+pub(crate) fn advance_frame<F: FrameSource>(mut source: ResMut<F>) {
+    source.advance(1);
+}
+
+/// Reads the [`FrameSource`] the plugin was added with, for code that only has the `World`.
+#[derive(Resource, Clone, Copy)]
+pub(crate) struct FrameSourceReader(fn(&World) -> FrameNumber);
+
+impl FrameSourceReader {
+    pub(crate) fn new<F: FrameSource>() -> Self {
+        Self(|world| world.resource::<F>().frame())
+    }
+}
+
+/// The current frame of the [`FrameSource`], from outside a system.
+pub(crate) fn frame_in_world(world: &World) -> FrameNumber {
+    let reader = world
+        .get_resource::<FrameSourceReader>()
+        .expect("TimewarpPlugin must be added first");
+    (reader.0)(world)
+}
+
 impl Deref for GameClock {
     type Target = FrameNumber;
     fn deref(&self) -> &Self::Target {
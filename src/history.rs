@@ -0,0 +1,82 @@
+/// Reading component values at past frames, eg. for server-side lag compensation.
+///
+/// Works in either mode, but is mostly useful with [`TimewarpMode::Server`], where history is
+/// recorded without any rollback machinery.
+///
+/// ```rust,ignore
+/// fn hit_check(shots: Query<&Shot>, history: TimewarpHistory<Hitbox>) {
+///     for shot in shots.iter() {
+///         // rewind hitboxes to what the shooter saw when they fired
+///         for (entity, hitbox) in history.iter_at_frame(shot.client_frame) {
+///             if hitbox.intersects(&shot.ray) {
+///                 // ...
+///             }
+///         }
+///     }
+/// }
+/// ```
+use crate::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+/// Use in your systems to read recorded values of T, within the rollback window.
+/// `F` is the [`FrameSource`] the plugin was added with.
+#[derive(SystemParam)]
+pub struct TimewarpHistory<'w, 's, T: TimewarpComponent, F: FrameSource = GameClock> {
+    q: Query<'w, 's, (Entity, &'static ComponentHistory<T>)>,
+    game_clock: Res<'w, F>,
+}
+
+impl<T: TimewarpComponent, F: FrameSource> TimewarpHistory<'_, '_, T, F> {
+    /// the value of T for the entity at `frame`, if it had T then and the frame is still
+    /// in the buffer.
+    pub fn at_frame(&self, entity: Entity, frame: FrameNumber) -> Option<&T> {
+        let (_, ch) = self.q.get(entity).ok()?;
+        Self::value_at(ch, frame)
+    }
+    /// every entity that had T at `frame`, with its value then.
+    pub fn iter_at_frame(&self, frame: FrameNumber) -> impl Iterator<Item = (Entity, &T)> {
+        self.q
+            .iter()
+            .filter_map(move |(entity, ch)| Self::value_at(ch, frame).map(|val| (entity, val)))
+    }
+    /// the current frame, the newest one that can have been recorded.
+    pub fn frame(&self) -> FrameNumber {
+        self.game_clock.frame()
+    }
+
+    /// every entity's current value of T, relative to what it was at `baseline_frame`, eg. the
+    /// last frame a client acknowledged. See [`SnapshotDelta`].
+    pub fn delta_since(&self, baseline_frame: FrameNumber) -> SnapshotDelta<T>
+    where
+        T: TimewarpDelta,
+    {
+        let frame = self.frame();
+        let deltas = self
+            .q
+            .iter()
+            .filter_map(|(entity, ch)| {
+                let current = Self::value_at(ch, frame)?;
+                let delta = match Self::value_at(ch, baseline_frame) {
+                    Some(baseline) => match current.diff(baseline) {
+                        Some(delta) => ComponentDelta::Changed(delta),
+                        None => ComponentDelta::Unchanged,
+                    },
+                    None => ComponentDelta::Full(current.clone()),
+                };
+                Some((entity, delta))
+            })
+            .collect();
+        SnapshotDelta {
+            baseline_frame,
+            frame,
+            deltas,
+        }
+    }
+
+    fn value_at(ch: &ComponentHistory<T>, frame: FrameNumber) -> Option<&T> {
+        if !ch.alive_at_frame(frame) {
+            return None;
+        }
+        ch.at_frame(frame)
+    }
+}
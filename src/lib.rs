@@ -79,44 +79,67 @@
 //! commands.entity(e1).insert(historical_component);
 //! ```
 //!
+//! With input delay, remote inputs and spawns can arrive for future frames. An
+//! `InsertComponentAtFrame` for a future frame is held until the clock reaches it, then applied,
+//! including while resimulating.
+//!
+//! If your network layer gives you a whole frame's worth of updates at once, you can apply them
+//! together as a [`SnapshotBatch`], saying whether the batch contains the entire world or just
+//! some entities. Timewarp then picks the rollback frame itself, so you don't need to choose a
+//! `RollbackConsolidationStrategy`:
+//!
+//! ```rust,ignore
+//! let mut batch = SnapshotBatch::partial(update.frame);
+//! batch.add_component(entity, position);
+//! commands.add(batch);
+//! ```
+//!
 //! ### Systems configuration
 //!
+//! The [`GameClock`], or your own [`FrameSource`], is advanced before your game logic runs, so
+//! you don't need your own frame increment system. The `FixedTime` period is set from
+//! `TimewarpConfig::tick_rate` if you provide one, otherwise your own `FixedTime` is kept, or it
+//! defaults to 60 fps.
+//!
 //! Divide up your game systems so that during a rollback you still apply stored player input,
 //! but ignore stuff like sending network messages etc.
 //!
-//! During a rollback, the [`Rollback`] resource will exist. Use this in a `run_if` condition.
+//! Put systems that should only run when not resimulating in `TimewarpGameSet::SideEffect`,
+//! and the rest of the chain runs every frame, including during rollback/fast-forward:
 //!
 //! ```rust,ignore
-//! // Normal game loop when not doing a rollback/fast-forward
 //! app.add_systems(FixedUpdate,
 //!     (
-//!         frame_inc,
-//!         process_server_messages,
-//!         process_position_updates_from_server,
-//!         spawn_stuff,
-//!         read_player_inputs,
+//!         (
+//!             process_server_messages,
+//!             process_position_updates_from_server,
+//!             spawn_stuff,
+//!             read_player_inputs,
+//!         ).in_set(TimewarpGameSet::SideEffect),
 //!         apply_all_player_inputs_to_simulation_for_this_frame,
 //!         do_physics,
-//!         render,
+//!         render.in_set(TimewarpGameSet::SideEffect),
 //!         etc,
 //!     )
 //!     .chain()
 //!     .in_set(MySets::GameLogic)
-//!     .run_if(not(resource_exists::<Rollback>())) // NOT in rollback
-//! );
-//! // Abridged game loop for replaying frames during rollback/fast-forward
-//! app.add_systems(FixedUpdate,
-//!     (
-//!         frame_inc,
-//!         apply_all_player_inputs_to_simulation_for_this_frame,
-//!         do_physics,
-//!     )
-//!     .chain()
-//!     .in_set(MySets::GameLogic)
-//!     .run_if(resource_exists::<Rollback>()) // Rollback is happening.
 //! );
 //! ```
 //!
+//! There are also run conditions: `in_rollback()`, `not_in_rollback()`, `rollback_started()`,
+//! `rollback_just_completed()` and `resimulating_frame(frame)`. During a rollback, the
+//! [`Rollback`] resource will exist. Rollbacks also send `RollbackStarted`, `FrameResimulated`
+//! and `RollbackCompleted` events, saying what triggered them and how long they took.
+//!
+//! Gameplay events like "bullet hit" would fire again during resimulation. Register them with
+//! `app.add_timewarp_event::<BulletHit>()` and send them with a [`TimewarpEventWriter`]
+//! from systems that run during rollback too. Systems that don't run during rollback read
+//! [`TimewarpEventUpdate`] events, which are only sent for events that are new, or
+//! cancelled because they didn't happen after all.
+//!
+//! For sounds and particles, [`PredictedEffects`] does the same keyed by frame, entity and effect
+//! kind, and tells you straight away whether an effect is new or already playing.
+//!
 //! # Visual smoothing of errors
 //!
 //! Timewarp snaps the simulation state – ie. the value of a component at a specific frame simulated
@@ -132,6 +155,55 @@
 //! Typically this would be useful for some visual smoothing - you might gradually blend over the
 //! error distance with your sprite, even though the underlying physical simulation snapped correct.
 //!
+//! Resimulating writes to your components, so `Changed<T>` fires for all of them after a rollback.
+//! Systems that only care about values a rollback actually changed, such as render syncing, can
+//! query `Changed<ChangedAfterRollback<T>>` instead, see [`ChangedAfterRollback`].
+//!
+//! # Rollback hooks
+//!
+//! Components that can't simply be overwritten with a clone, like physics bodies with an
+//! engine-side handle, can be registered with `app.register_rollback_with_hooks::<T>(hooks)`.
+//! The [`RollbackHooks`] callbacks run when timewarp restores, reinserts, removes or records the
+//! component, and get the entity's `EntityCommands` to fix up other state.
+//!
+//! # Peer-to-peer sessions
+//!
+//! Without a server, add a [`TimewarpP2pPlugin`] and exchange inputs between peers via its
+//! [`P2pSession`]. Remote inputs are predicted until they arrive, and a rollback is requested
+//! when one contradicts the prediction. History older than the [`P2pConfirmedFrame`], the newest
+//! frame everyone's inputs are known for, is discarded.
+//!
+//! # Determinism checks
+//!
+//! It's easy to accidentally write game logic that doesn't give the same result when
+//! resimulated, for example by reading a resource that isn't rolled back. Set
+//! [`TimewarpConfig::with_determinism_check`] to resimulate the last few frames every so often
+//! without any new data, and compare the result to what was originally simulated. The first
+//! divergence is sent as a [`DeterminismCheckFailed`] event, or panics if configured to.
+//!
+//! # Server mode
+//!
+//! The authoritative server doesn't rollback, but can still use the recorded history, eg. to
+//! rewind hitboxes for lag compensation. Use [`TimewarpConfig::with_mode`] with
+//! [`TimewarpMode::Server`] and only the recording systems are installed, no `ServerSnapshot`
+//! components are added. Read past values with the [`TimewarpHistory`] system param.
+//!
+//! # Delta-compressed snapshots
+//!
+//! Implement [`TimewarpDelta`] for a component to send only what changed since the last frame
+//! a client acknowledged. On the server, [`TimewarpHistory::delta_since`] diffs against the
+//! recorded history, and on the client [`SnapshotDelta::add_to_batch`] rebuilds full values
+//! from the `ServerSnapshot` at the acknowledged frame, into a [`SnapshotBatch`] for the new frame.
+//!
+//! # Extrapolating missing entities
+//!
+//! When a partial snapshot doesn't include some entities, their predictions aren't corrected and
+//! can drift. Implement [`TimewarpExtrapolate`] (eg. for `Position`, using `Velocity`) and
+//! `register_extrapolation`, and those entities get an estimated value for the snapshot frame,
+//! from their last snapshot. If it diverges from our prediction, a rollback is requested like for
+//! a real snapshot. Estimates are limited to [`TimewarpConfig::with_max_extrapolation_age`]
+//! frames past the last snapshot.
+//!
 //! ## Testing various edge cases
 //!
 //! TODO: I don't know how to link rustdocs to integration tests..
@@ -169,11 +241,16 @@
 //! Removing components is hopefully a sufficient substitute for immediately despawning, however
 //! be aware the entity id will still exist until finally despawned.
 //!
+//! A `DespawnMarker` on a parent is also added to all its children, so they are cleaned up on the
+//! same frame. If you call `app.register_rollback_hierarchy()`, parent/child relationships of
+//! timewarp entities are rolled back too, see [`TimewarpParent`].
+//!
 //! ## Caveats:
 //!
 //! - Developing this alongside a simple game, so this is based on what I need for my attempt at
 //!   a server-authoritative multiplayer game.
-//! - Currently requires you to use [`GameClock`] struct from this crate as frame counter.
+//! - Uses the [`GameClock`] struct from this crate as frame counter, or your own resource
+//!   if it implements [`FrameSource`].
 //! - Littered with a variety of debug logging, set your log level accordingly
 //! - Unoptimized: clones components each frame without checking if they've changed.
 //! - Doesn't rollback resources or other things, just (registered) component data.
@@ -181,29 +258,57 @@
 //! - I'm using a patched version of `bevy_xpbd` at the mo, to make `Collider` impl `PartialEq`
 //!   (PRs sent..)
 //!
+mod alive_ranges;
 pub(crate) mod components;
+#[cfg(feature = "debug_ui")]
+mod debug_ui;
 mod error;
 mod frame_buffer;
 mod game_clock;
+mod history;
+mod p2p;
+mod predicted_effects;
+#[cfg(feature = "replicon")]
+mod replicon;
 pub(crate) mod resources;
+mod rollback_hooks;
+mod run_conditions;
+mod snapshot_batch;
+mod snapshot_delta;
 pub(crate) mod systems;
+mod timewarp_events;
 mod traits;
 
 pub mod prelude {
+    pub use crate::alive_ranges::*;
     pub use crate::components::*;
+    #[cfg(feature = "debug_ui")]
+    pub use crate::debug_ui::*;
     pub use crate::error::*;
     pub use crate::frame_buffer::*;
     pub use crate::game_clock::*;
+    pub use crate::history::*;
+    pub use crate::p2p::*;
+    pub use crate::predicted_effects::*;
+    #[cfg(feature = "replicon")]
+    pub use crate::replicon::*;
     pub use crate::resources::*;
+    pub use crate::rollback_hooks::*;
+    pub use crate::run_conditions::*;
+    pub use crate::snapshot_batch::*;
+    pub use crate::snapshot_delta::*;
+    pub use crate::timewarp_events::*;
     pub use crate::traits::*;
     pub use crate::TimewarpPlugin;
     pub type FrameNumber = u32;
+    pub use crate::TimewarpGameSet;
     pub use crate::TimewarpPostfixSet;
     pub use crate::TimewarpPrefixSet;
 }
 
 use bevy::prelude::*;
 use prelude::*;
+use std::marker::PhantomData;
 
 /// bevy_timewarp's pre-game systems run in these sets, which get configured to run
 /// before the main game logic (the set for which is provided in the plugin setup)
@@ -215,6 +320,8 @@ pub enum TimewarpPrefixSet {
     StartRollback,
     UnwrapBlueprints,
     Last,
+    /// advances the [`FrameSource`] to the frame about to be simulated
+    AdvanceFrame,
 }
 
 /// bevy_timewarp's post-game systems run in these sets, which get configured to run
@@ -227,24 +334,73 @@ pub enum TimewarpPostfixSet {
     Last,
 }
 
-pub struct TimewarpPlugin {
+/// Label your game systems with these, so one chain works for normal frames and resimulation.
+/// Both run after the frame is advanced and before the postfix systems, same as the game logic
+/// sets in your [`TimewarpConfig`], so they can be used with or without those.
+/// Systems in neither set always run, same as `Simulation`.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimewarpGameSet {
+    /// affects the simulation, so runs during rollback too. eg, physics, applying inputs.
+    Simulation,
+    /// only runs when not resimulating. eg, sending network messages, reading local input.
+    SideEffect,
+}
+
+/// Uses our [`GameClock`] as the frame counter, unless you provide your own [`FrameSource`].
+pub struct TimewarpPlugin<F: FrameSource = GameClock> {
     config: TimewarpConfig,
+    _frame_source: PhantomData<F>,
 }
 
 impl TimewarpPlugin {
     pub fn new(timewarp_config: TimewarpConfig) -> Self {
+        Self::with_frame_source(timewarp_config)
+    }
+}
+
+impl<F: FrameSource> TimewarpPlugin<F> {
+    /// Reads and rewinds the frame number in your `F` resource, which is initialised from
+    /// `FromWorld` unless you've already inserted it.
+    /// Register components with [`TimewarpApp`] so their systems read `F` too.
+    pub fn with_frame_source(timewarp_config: TimewarpConfig) -> Self {
         Self {
             config: timewarp_config,
+            _frame_source: PhantomData,
         }
     }
 }
 
-impl Plugin for TimewarpPlugin {
+impl<F: FrameSource + FromWorld> Plugin for TimewarpPlugin<F> {
     fn build(&self, app: &mut App) {
+        app.add_systems(
+            self.config.schedule(),
+            game_clock::advance_frame::<F>.in_set(TimewarpPrefixSet::AdvanceFrame),
+        );
+        app.init_resource::<F>()
+            .insert_resource(game_clock::FrameSourceReader::new::<F>());
+        match self.config.tick_rate() {
+            Some(tick_rate) => {
+                app.insert_resource(FixedTime::new_from_secs(1.0 / tick_rate));
+            }
+            None => {
+                if !app.world.contains_resource::<FixedTime>() {
+                    app.insert_resource(FixedTime::new_from_secs(1.0 / 60.0));
+                }
+            }
+        }
         app.insert_resource(self.config.clone())
             // RollbackRequest events are drained manually in `consolidate_rollback_requests`
             .init_resource::<Events<RollbackRequest>>()
             .insert_resource(RollbackStats::default())
+            .init_resource::<SnapshotBatchFrames>()
+            .init_resource::<resources::OverflowSnapEntities>()
+            .add_event::<RollbackOverflow>()
+            .add_event::<RemapTimewarpEntities>()
+            .add_event::<RollbackStarted>()
+            .add_event::<FrameResimulated>()
+            .add_event::<RollbackCompleted>()
+            .add_event::<DeterminismCheckFailed>()
+            .init_resource::<resources::FirstDivergence>()
             //
             // PREFIX
             //
@@ -260,6 +416,7 @@ impl Plugin for TimewarpPlugin {
                     TimewarpPrefixSet::UnwrapBlueprints,
                     TimewarpPrefixSet::Last,
                     // -- apply_deferred -- //
+                    TimewarpPrefixSet::AdvanceFrame,
                 )
                     .chain(),
             )
@@ -287,31 +444,31 @@ impl Plugin for TimewarpPlugin {
             //
             .add_systems(
                 self.config.schedule(),
-                (|game_clock: Res<GameClock>, rb: Option<Res<Rollback>>| {
+                (|game_clock: Res<F>, rb: Option<Res<Rollback>>| {
                     trace!(
                         "--------------------- Prefix::First for {} {rb:?}",
-                        **game_clock + 1
+                        game_clock.frame() + 1
                     )
                 })
                 .in_set(TimewarpPrefixSet::First),
             )
             .add_systems(
                 self.config.schedule(),
-                (|game_clock: Res<GameClock>, rb: Option<Res<Rollback>>| {
-                    trace!("Prefix::Last for {} {rb:?}", **game_clock + 1)
+                (|game_clock: Res<F>, rb: Option<Res<Rollback>>| {
+                    trace!("Prefix::Last for {} {rb:?}", game_clock.frame() + 1)
                 })
                 .in_set(TimewarpPrefixSet::Last),
             )
             .add_systems(
                 self.config.schedule(),
-                (|game_clock: Res<GameClock>, rb: Option<Res<Rollback>>| {
+                (|game_clock: Res<F>, rb: Option<Res<Rollback>>| {
                     trace!("Postfix::First {game_clock:?} {rb:?}");
                 })
                 .in_set(TimewarpPostfixSet::First),
             )
             .add_systems(
                 self.config.schedule(),
-                (|game_clock: Res<GameClock>, rb: Option<Res<Rollback>>| {
+                (|game_clock: Res<F>, rb: Option<Res<Rollback>>| {
                     trace!("Postfix::Last {game_clock:?} {rb:?}");
                 })
                 .in_set(TimewarpPostfixSet::Last),
@@ -321,30 +478,7 @@ impl Plugin for TimewarpPlugin {
             //
             .add_systems(
                 self.config.schedule(),
-                systems::sanity_check.in_set(TimewarpPrefixSet::First),
-            )
-            .add_systems(
-                self.config.schedule(),
-                (
-                    systems::prefix_in_rollback::check_for_rollback_completion,
-                    apply_deferred,
-                )
-                    .chain()
-                    .in_set(TimewarpPrefixSet::InRollback),
-            )
-            .add_systems(
-                self.config.schedule(),
-                (
-                    systems::prefix_not_in_rollback::consolidate_rollback_requests,
-                    apply_deferred,
-                )
-                    .chain()
-                    .in_set(TimewarpPrefixSet::NotInRollback),
-            )
-            .add_systems(
-                self.config.schedule(),
-                systems::prefix_start_rollback::rollback_initiated
-                    .in_set(TimewarpPrefixSet::StartRollback),
+                systems::sanity_check::<F>.in_set(TimewarpPrefixSet::First),
             )
             .add_systems(
                 self.config.schedule(),
@@ -365,7 +499,16 @@ impl Plugin for TimewarpPlugin {
             )
             .add_systems(
                 self.config.schedule(),
-                systems::postfix_last::despawn_entities_with_elapsed_despawn_marker
+                (
+                    systems::hierarchy::propagate_despawn_marker_to_children,
+                    apply_deferred,
+                )
+                    .chain()
+                    .in_set(TimewarpPostfixSet::First),
+            )
+            .add_systems(
+                self.config.schedule(),
+                systems::postfix_last::despawn_entities_with_elapsed_despawn_marker::<F>
                     .in_set(TimewarpPostfixSet::Last),
             )
             // flush commands at the very end, since they may be referencing entities which
@@ -378,18 +521,74 @@ impl Plugin for TimewarpPlugin {
             // so for now, have to use the deprecated configure_set instead of configure_sets.
             // assume this is because bevy's API is in transitional phase in this regards..
             // https://github.com/bevyengine/bevy/pull/9247
-            // the specified first set must be after our TW prefix runs
+            // the specified first set must be after our TW prefix runs, and the frame advanced
             .configure_set(
                 self.config.schedule(),
-                self.config.first_set().after(TimewarpPrefixSet::Last),
+                self.config
+                    .first_set()
+                    .after(TimewarpPrefixSet::AdvanceFrame),
+            )
+            // like the game logic sets, these run after the frame advanced and before the postfix,
+            // so systems only need to be in one of them.
+            .configure_sets(
+                self.config.schedule(),
+                (
+                    TimewarpGameSet::Simulation,
+                    TimewarpGameSet::SideEffect.run_if(not_in_rollback()),
+                )
+                    .after(TimewarpPrefixSet::AdvanceFrame)
+                    .before(TimewarpPostfixSet::First),
             )
             // the specified last set must be before the TW postfix runs.
             .configure_set(
                 self.config.schedule(),
                 self.config.last_set().before(TimewarpPostfixSet::First),
+            );
+
+        if self.config.mode() == TimewarpMode::Server {
+            // the server never rolls back, it only records history
+            return;
+        }
+        app.add_systems(
+            self.config.schedule(),
+            (
+                systems::prefix_in_rollback::check_for_rollback_completion::<F>,
+                apply_deferred,
             )
-            //
-            .insert_resource(FixedTime::new_from_secs(1.0 / 60.0))
-            .insert_resource(GameClock::new());
+                .chain()
+                .in_set(TimewarpPrefixSet::InRollback),
+        )
+        .add_systems(
+            self.config.schedule(),
+            (
+                systems::prefix_not_in_rollback::request_determinism_check::<F>,
+                systems::prefix_not_in_rollback::consolidate_rollback_requests::<F>,
+                apply_deferred,
+            )
+                .chain()
+                .in_set(TimewarpPrefixSet::NotInRollback),
+        )
+        .add_systems(
+            self.config.schedule(),
+            (
+                systems::prefix_start_rollback::rollback_initiated::<F>,
+                systems::prefix_start_rollback::mark_entities_skipping_resimulation,
+            )
+                .in_set(TimewarpPrefixSet::StartRollback),
+        )
+        .add_systems(
+            self.config.schedule(),
+            (
+                systems::postfix_in_rollback::send_frame_resimulated::<F>,
+                systems::postfix_in_rollback::report_determinism_check::<F>,
+            )
+                .in_set(TimewarpPostfixSet::InRollback),
+        )
+        .add_systems(
+            self.config.schedule(),
+            systems::postfix_last::adapt_rollback_window::<F>
+                .run_if(not(resource_exists::<Rollback>()))
+                .in_set(TimewarpPostfixSet::Last),
+        );
     }
 }
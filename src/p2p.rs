@@ -0,0 +1,300 @@
+//! Peer-to-peer sessions, where only inputs are exchanged. (GGPO-style)
+//!
+//! Instead of snapshots from a server, every peer simulates the whole game from everyone's inputs.
+//! Remote inputs that haven't arrived yet are predicted by repeating the last one we have.
+//! When an input arrives that differs from what we predicted, a rollback resimulates from that
+//! frame using the usual [`Rollback`] machinery.
+//!
+//! The newest frame for which all peers' inputs are known is the confirmed frame. It will never
+//! be rolled back to, so component history older than it is discarded.
+//!
+//! ```rust,ignore
+//! app.add_plugins(TimewarpP2pPlugin::<MyInput>::new(2, local_player));
+//!
+//! // in your game logic, when not in rollback:
+//! fn add_local_input(mut session: ResMut<P2pSession<MyInput>>, game_clock: Res<GameClock>) {
+//!     session.add_local_input(game_clock.frame(), read_keyboard());
+//! }
+//! // and always:
+//! fn apply_inputs(mut session: ResMut<P2pSession<MyInput>>, game_clock: Res<GameClock>, ..) {
+//!     let input = session.input(player, game_clock.frame());
+//! }
+//! // send `session.drain_outgoing()` to the other peers, and pass what they send to `session.receive()`
+//! ```
+use crate::prelude::*;
+use crate::systems::{
+    postfix_last::adapt_rollback_window, prefix_not_in_rollback::consolidate_rollback_requests,
+};
+use bevy::prelude::*;
+use std::marker::PhantomData;
+
+/// Bounds for inputs exchanged in a [`P2pSession`].
+/// `Default` is predicted for peers we haven't had any inputs from yet.
+pub trait TimewarpInput:
+    Clone + PartialEq + Default + Send + Sync + std::fmt::Debug + 'static
+{
+}
+
+impl<I> TimewarpInput for I where
+    I: Clone + PartialEq + Default + Send + Sync + std::fmt::Debug + 'static
+{
+}
+
+/// Index of a peer in a session
+pub type PlayerHandle = usize;
+
+/// One peer's input for a frame, to be sent to the other peers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct P2pInputMessage<I: TimewarpInput> {
+    pub player: PlayerHandle,
+    pub frame: FrameNumber,
+    pub input: I,
+}
+
+/// The newest frame for which all peers' inputs are known.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Deref)]
+pub struct P2pConfirmedFrame(pub FrameNumber);
+
+#[derive(Debug)]
+struct PeerInputs<I: TimewarpInput> {
+    /// inputs we've received (or added, for the local player)
+    received: FrameBuffer<I>,
+    /// inputs we simulated with before the real one arrived
+    predicted: FrameBuffer<I>,
+    /// newest frame we have this peer's inputs for, and all before it
+    confirmed: FrameNumber,
+}
+
+/// Inputs for every peer in a session.
+#[derive(Resource, Debug)]
+pub struct P2pSession<I: TimewarpInput> {
+    local_player: PlayerHandle,
+    peers: Vec<PeerInputs<I>>,
+    outgoing: Vec<P2pInputMessage<I>>,
+    /// oldest frame a received input contradicted our prediction for
+    mispredicted_from: Option<FrameNumber>,
+}
+
+impl<I: TimewarpInput> P2pSession<I> {
+    /// `len` is how many frames of inputs are buffered, at least the rollback window.
+    pub fn new(num_players: usize, local_player: PlayerHandle, len: usize) -> Self {
+        assert!(
+            local_player < num_players,
+            "local player must be one of the players"
+        );
+        Self {
+            local_player,
+            peers: (0..num_players)
+                .map(|_| PeerInputs {
+                    received: FrameBuffer::with_capacity(len, "P2pInputs"),
+                    predicted: FrameBuffer::with_capacity(len, "P2pPredicted"),
+                    confirmed: 0,
+                })
+                .collect(),
+            outgoing: Vec::new(),
+            mispredicted_from: None,
+        }
+    }
+    /// How many frames of inputs are buffered.
+    pub fn buffer_len(&self) -> usize {
+        self.peers[0].received.capacity()
+    }
+    /// Change how many frames of inputs are buffered, dropping the oldest if it shrinks.
+    pub fn set_buffer_len(&mut self, len: usize) {
+        for peer in self.peers.iter_mut() {
+            peer.received.set_capacity(len);
+            peer.predicted.set_capacity(len);
+        }
+    }
+    pub fn local_player(&self) -> PlayerHandle {
+        self.local_player
+    }
+    pub fn num_players(&self) -> usize {
+        self.peers.len()
+    }
+    /// The newest frame for which all peers' inputs are known.
+    pub fn confirmed_frame(&self) -> FrameNumber {
+        self.peers.iter().map(|p| p.confirmed).min().unwrap_or(0)
+    }
+    /// Newest frame we have all of this player's inputs up to.
+    pub fn confirmed_frame_for(&self, player: PlayerHandle) -> FrameNumber {
+        self.peers[player].confirmed
+    }
+    /// Adds the local player's input for `frame`, and queues it to be sent to the other peers.
+    pub fn add_local_input(&mut self, frame: FrameNumber, input: I) {
+        let player = self.local_player;
+        if self.peers[player].received.get(frame).is_some() {
+            // already added, eg. while resimulating
+            return;
+        }
+        if let Err(err) = self.insert_input(player, frame, input.clone()) {
+            warn!("{err:?} adding local input @ {frame}");
+            return;
+        }
+        self.outgoing.push(P2pInputMessage {
+            player,
+            frame,
+            input,
+        });
+    }
+    /// Local inputs to send to the other peers.
+    pub fn drain_outgoing(&mut self) -> Vec<P2pInputMessage<I>> {
+        std::mem::take(&mut self.outgoing)
+    }
+    /// An input from another peer. If it differs from what we predicted for that frame, a
+    /// rollback will be requested.
+    pub fn receive(&mut self, msg: P2pInputMessage<I>) -> Result<(), TimewarpError> {
+        let P2pInputMessage {
+            player,
+            frame,
+            input,
+        } = msg;
+        if self.peers[player].received.get(frame).is_some() {
+            return Ok(());
+        }
+        if let Some(predicted) = self.peers[player].predicted.get(frame) {
+            if *predicted != input {
+                debug!(
+                    "P2P misprediction for player {player} @ {frame}: {predicted:?} != {input:?}"
+                );
+                self.mispredicted_from = Some(
+                    self.mispredicted_from
+                        .map_or(frame, |earliest| earliest.min(frame)),
+                );
+            }
+        }
+        self.insert_input(player, frame, input)
+    }
+    /// The input to simulate `frame` with: the real one if we have it, otherwise a prediction.
+    pub fn input(&mut self, player: PlayerHandle, frame: FrameNumber) -> I {
+        let peer = &mut self.peers[player];
+        if let Some(input) = peer.received.get(frame) {
+            return input.clone();
+        }
+        // repeat the newest input we have
+        let predicted = peer
+            .received
+            .get(peer.confirmed.min(frame))
+            .cloned()
+            .unwrap_or_default();
+        if let Err(err) = peer.predicted.insert(frame, predicted.clone()) {
+            warn!("{err:?} recording predicted input for player {player} @ {frame}");
+        }
+        predicted
+    }
+    /// Inputs for every player, in player order.
+    pub fn inputs(&mut self, frame: FrameNumber) -> Vec<I> {
+        (0..self.peers.len())
+            .map(|player| self.input(player, frame))
+            .collect()
+    }
+    fn insert_input(
+        &mut self,
+        player: PlayerHandle,
+        frame: FrameNumber,
+        input: I,
+    ) -> Result<(), TimewarpError> {
+        let peer = &mut self.peers[player];
+        peer.received.insert(frame, input)?;
+        while peer.received.get(peer.confirmed + 1).is_some() {
+            peer.confirmed += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Adds a [`P2pSession`] for inputs of type `I`. Add after the [`TimewarpPlugin`].
+pub struct TimewarpP2pPlugin<I: TimewarpInput, F: FrameSource = GameClock> {
+    num_players: usize,
+    local_player: PlayerHandle,
+    _input: PhantomData<I>,
+    _frame_source: PhantomData<F>,
+}
+
+impl<I: TimewarpInput> TimewarpP2pPlugin<I> {
+    pub fn new(num_players: usize, local_player: PlayerHandle) -> Self {
+        Self::with_frame_source(num_players, local_player)
+    }
+}
+
+impl<I: TimewarpInput, F: FrameSource> TimewarpP2pPlugin<I, F> {
+    /// For apps using `TimewarpPlugin::<F>::with_frame_source`.
+    pub fn with_frame_source(num_players: usize, local_player: PlayerHandle) -> Self {
+        Self {
+            num_players,
+            local_player,
+            _input: PhantomData,
+            _frame_source: PhantomData,
+        }
+    }
+}
+
+impl<I: TimewarpInput, F: FrameSource> Plugin for TimewarpP2pPlugin<I, F> {
+    fn build(&self, app: &mut App) {
+        let config = app
+            .world
+            .get_resource::<TimewarpConfig>()
+            .expect("TimewarpPlugin must be added before TimewarpP2pPlugin");
+        let schedule = config.schedule();
+        app.insert_resource(P2pSession::<I>::new(
+            self.num_players,
+            self.local_player,
+            session_len(config),
+        ));
+        app.init_resource::<P2pConfirmedFrame>();
+        app.add_systems(
+            schedule.clone(),
+            (
+                request_rollback_for_mispredicted_inputs::<I>,
+                update_confirmed_frame::<I>,
+            )
+                .chain()
+                .before(consolidate_rollback_requests::<F>)
+                .in_set(TimewarpPrefixSet::NotInRollback),
+        );
+        app.add_systems(
+            schedule,
+            resize_session_to_rollback_window::<I>
+                .after(adapt_rollback_window::<F>)
+                .run_if(resource_changed::<TimewarpConfig>())
+                .in_set(TimewarpPostfixSet::Last),
+        );
+    }
+}
+
+/// inputs are kept a little longer than the window, so late ones can still be compared
+fn session_len(config: &TimewarpConfig) -> usize {
+    config.rollback_window() as usize * 2
+}
+
+/// Resizes the input buffers when the rollback window changes.
+pub(crate) fn resize_session_to_rollback_window<I: TimewarpInput>(
+    mut session: ResMut<P2pSession<I>>,
+    config: Res<TimewarpConfig>,
+) {
+    let len = session_len(&config);
+    if session.buffer_len() != len {
+        session.set_buffer_len(len);
+    }
+}
+
+/// If a remote input contradicted our prediction, resimulate from that frame.
+pub(crate) fn request_rollback_for_mispredicted_inputs<I: TimewarpInput>(
+    mut session: ResMut<P2pSession<I>>,
+    mut rb_ev: ResMut<Events<RollbackRequest>>,
+) {
+    if let Some(frame) = session.mispredicted_from.take() {
+        debug!("Triggering rollback due to P2P input misprediction @ {frame}");
+        rb_ev.send(RollbackRequest::resimulate_this_frame_onwards(frame));
+    }
+}
+
+pub(crate) fn update_confirmed_frame<I: TimewarpInput>(
+    session: Res<P2pSession<I>>,
+    mut confirmed: ResMut<P2pConfirmedFrame>,
+) {
+    let frame = session.confirmed_frame();
+    if **confirmed != frame {
+        confirmed.0 = frame;
+    }
+}
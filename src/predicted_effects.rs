@@ -0,0 +1,60 @@
+/// Cosmetic effects (sounds, particles..) spawned from predicted frames.
+///
+/// Effects are keyed by (frame, entity, kind). When a rollback resimulates a frame, emitting the
+/// same effect again is suppressed, since it's already playing. Effects that don't happen after
+/// resimulating are reported as cancelled, so you can fade them out.
+///
+/// ```rust,ignore
+/// app.add_predicted_effects::<Effect>();
+///
+/// fn explosions(q: Query<Entity, Added<Exploded>>, mut effects: PredictedEffects<Effect>) {
+///     for entity in q.iter() {
+///         if effects.emit(entity, Effect::Boom) {
+///             // only spawned the first time, not when resimulating
+///             spawn_boom_particles(entity);
+///         }
+///     }
+/// }
+///
+/// fn fade_cancelled(mut updates: EventReader<PredictedEffectUpdate<Effect>>) {
+///     for update in updates.iter() {
+///         if let TimewarpEventUpdate::Cancelled { event, .. } = update {
+///             fade_out_effects_for(event.entity, &event.kind);
+///         }
+///     }
+/// }
+/// ```
+use crate::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+/// One effect on one entity, the frame comes from when it's emitted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PredictedEffect<K: TimewarpEvent> {
+    pub entity: Entity,
+    pub kind: K,
+}
+
+/// Sent for effects that are new, or cancelled after a rollback.
+pub type PredictedEffectUpdate<K> = TimewarpEventUpdate<PredictedEffect<K>>;
+
+/// Use in your game systems to emit predicted effects, including during rollback.
+/// `F` is the [`FrameSource`] the plugin was added with.
+#[derive(SystemParam)]
+pub struct PredictedEffects<'w, K: TimewarpEvent, F: FrameSource = GameClock> {
+    events: ResMut<'w, TimewarpEvents<PredictedEffect<K>>>,
+    game_clock: Res<'w, F>,
+}
+
+impl<K: TimewarpEvent, F: FrameSource> PredictedEffects<'_, K, F> {
+    /// records the effect for the current frame.
+    /// returns false if it was already emitted for this frame, ie we're resimulating and it
+    /// still happened, so don't spawn it again.
+    pub fn emit(&mut self, entity: Entity, kind: K) -> bool {
+        let effect = PredictedEffect { entity, kind };
+        let is_new = !self
+            .events
+            .already_emitted(self.game_clock.frame(), &effect);
+        self.events.send(effect);
+        is_new
+    }
+}
@@ -0,0 +1,126 @@
+//! Optional glue for using timewarp with [bevy_replicon](https://crates.io/crates/bevy_replicon).
+//!
+//! Enable the `replicon` cargo feature, then:
+//!
+//! ```rust,ignore
+//! app.add_plugins(TimewarpPlugin::new(tw_config));
+//! app.add_plugins(TimewarpRepliconPlugin::new());
+//! // instead of app.replicate::<Position>():
+//! app.replicate_with_timewarp::<Position>();
+//! app.register_rollback::<Position>();
+//! ```
+//!
+//! Replicated values are written into the [`ServerSnapshot`] at the frame given by the
+//! `RepliconTick` they arrived with, so timewarp can decide if a rollback is needed.
+use crate::prelude::*;
+use bevy::{ecs::world::EntityMut, prelude::*};
+use bevy_replicon::{
+    bincode::{self, DefaultOptions, Options},
+    prelude::*,
+    renet::Bytes,
+    replicon_core::replication_rules::{remove_component, serialize_component},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{io::Cursor, marker::PhantomData};
+
+/// Deserializes a component sent by replicon, and inserts it at the frame matching the
+/// `RepliconTick` it was sent for, via [`TimewarpEntityMutTraits::insert_component_at_frame`].
+///
+/// Use this instead of writing a `deserialize_*_timewarp` fn per component.
+pub fn deserialize_component_timewarp<T: TimewarpComponent + DeserializeOwned>(
+    entity: &mut EntityMut,
+    _entity_map: &mut ServerEntityMap,
+    cursor: &mut Cursor<Bytes>,
+    tick: RepliconTick,
+) -> bincode::Result<()> {
+    let component: T = DefaultOptions::new().deserialize_from(cursor)?;
+    if let Err(err) = entity.insert_component_at_frame(tick.get(), &component) {
+        warn!(
+            "{err:?} {:?} couldn't insert {} @ {tick:?}",
+            entity.id(),
+            std::any::type_name::<T>()
+        );
+    }
+    Ok(())
+}
+
+/// trait for registering timewarp components with replicon.
+pub trait TimewarpRepliconTraits {
+    /// like replicon's `replicate::<T>()`, but received values are inserted at the frame they
+    /// were sent for, rather than overwriting the current component value.
+    fn replicate_with_timewarp<T: TimewarpComponent + Serialize + DeserializeOwned>(
+        &mut self,
+    ) -> &mut Self;
+}
+
+impl TimewarpRepliconTraits for App {
+    fn replicate_with_timewarp<T: TimewarpComponent + Serialize + DeserializeOwned>(
+        &mut self,
+    ) -> &mut Self {
+        self.replicate_with::<T>(
+            serialize_component::<T>,
+            deserialize_component_timewarp::<T>,
+            remove_component::<T>,
+        )
+    }
+}
+
+/// Keeps replicon's tick in lockstep with the [`FrameSource`], so the `RepliconTick` you receive
+/// in deserializers matches the frame the values were simulated for.
+///
+/// Also sets the [`RollbackConsolidationStrategy`] to match replicon's whole-world updates.
+/// Add this after the [`TimewarpPlugin`], and set replicon's `TickPolicy` to `Manual` on the server.
+pub struct TimewarpRepliconPlugin<F: FrameSource = GameClock> {
+    _frame_source: PhantomData<F>,
+}
+
+impl TimewarpRepliconPlugin {
+    pub fn new() -> Self {
+        Self::with_frame_source()
+    }
+}
+
+impl Default for TimewarpRepliconPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: FrameSource> TimewarpRepliconPlugin<F> {
+    /// For apps using `TimewarpPlugin::<F>::with_frame_source`.
+    pub fn with_frame_source() -> Self {
+        Self {
+            _frame_source: PhantomData,
+        }
+    }
+}
+
+impl<F: FrameSource> Plugin for TimewarpRepliconPlugin<F> {
+    fn build(&self, app: &mut App) {
+        let mut config = app
+            .world
+            .get_resource_mut::<TimewarpConfig>()
+            .expect("TimewarpPlugin must be added before TimewarpRepliconPlugin");
+        // replicon sends entire-world updates per tick, see consolidate_rollback_requests
+        config.set_consolidation_strategy(RollbackConsolidationStrategy::Newest);
+        let schedule = config.schedule();
+        app.add_systems(
+            schedule,
+            sync_replicon_tick::<F>
+                .run_if(resource_exists::<RepliconTick>())
+                .run_if(not(resource_exists::<Rollback>()))
+                .in_set(TimewarpPostfixSet::First),
+        );
+    }
+}
+
+/// advance the replicon tick to match the game clock. only the server has a `RepliconTick`.
+pub(crate) fn sync_replicon_tick<F: FrameSource>(
+    game_clock: Res<F>,
+    mut replicon_tick: ResMut<RepliconTick>,
+) {
+    let delta = game_clock.frame().saturating_sub(replicon_tick.get());
+    if delta > 0 {
+        replicon_tick.increment_by(delta);
+    }
+}
@@ -1,7 +1,12 @@
-use crate::FrameNumber;
+use crate::{
+    prelude::{ComponentHistory, RollbackIsland, SnapshotCompleteness, TimewarpComponent},
+    FrameNumber,
+};
+use bevy::utils::Instant;
 use bevy::{
     ecs::schedule::{BoxedSystemSet, ScheduleLabel},
     prelude::*,
+    utils::{HashMap, HashSet},
 };
 use std::{ops::Range, time::Duration};
 
@@ -14,8 +19,100 @@ pub enum RollbackConsolidationStrategy {
     Newest,
 }
 
+/// what to do when a rollback is requested to a frame older than the rollback window allows
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RollbackOverflowPolicy {
+    /// panic, since all sorts of things would fail spectacularly otherwise.
+    #[default]
+    Panic,
+    /// rollback to the oldest frame we can, and resimulate from there.
+    Clamp,
+    /// don't rollback, ignoring the snapshot. Counted in `RollbackStats::rollback_overflows`.
+    Drop,
+    /// don't rollback, just snap the components straight to their snapshot values.
+    Snap,
+    /// don't rollback, but send a [`RollbackOverflow`] event so your game can ask for a resync.
+    EmitEvent,
+}
+
+/// Bounds for resizing the rollback window at runtime, based on how old incoming snapshots are.
+/// Each frame, the age of the newest snapshot received is sampled. The window is set to the
+/// largest age sampled in the last `sample_frames` frames, plus `margin`, clamped to `min..=max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveRollbackWindow {
+    pub min: FrameNumber,
+    pub max: FrameNumber,
+    /// extra frames on top of the observed snapshot age, to absorb jitter
+    pub margin: FrameNumber,
+    /// how many recent frames' snapshot ages to consider
+    pub sample_frames: usize,
+}
+
+impl AdaptiveRollbackWindow {
+    pub fn new(min: FrameNumber, max: FrameNumber) -> Self {
+        Self {
+            min,
+            max,
+            margin: 2,
+            sample_frames: 120,
+        }
+    }
+    pub fn with_margin(mut self, margin: FrameNumber) -> Self {
+        self.margin = margin;
+        self
+    }
+    pub fn with_sample_frames(mut self, sample_frames: usize) -> Self {
+        self.sample_frames = sample_frames;
+        self
+    }
+    /// the window needed to roll back to the frame after a snapshot this many frames old.
+    pub fn window_for_age(&self, snapshot_age: FrameNumber) -> FrameNumber {
+        (snapshot_age + 1 + self.margin).clamp(self.min, self.max)
+    }
+}
+
+/// Settings for checking the game simulation is deterministic.
+///
+/// Every `every_frames` frames, we roll back `depth` frames without any new data, resimulate,
+/// and compare every registered component against the values recorded the first time.
+/// The first divergence is reported with a [`DeterminismCheckFailed`] event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeterminismCheck {
+    pub every_frames: FrameNumber,
+    pub depth: FrameNumber,
+    /// panic instead of just sending the event, useful in tests
+    pub panic_on_failure: bool,
+}
+
+impl DeterminismCheck {
+    pub fn new(every_frames: FrameNumber, depth: FrameNumber) -> Self {
+        Self {
+            every_frames,
+            depth,
+            panic_on_failure: false,
+        }
+    }
+    pub fn with_panic_on_failure(mut self, enabled: bool) -> Self {
+        self.panic_on_failure = enabled;
+        self
+    }
+}
+
+/// Whether we're a client that rolls back, or a server that only records history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimewarpMode {
+    /// records history, applies server snapshots and rolls back to resimulate.
+    #[default]
+    Client,
+    /// never rolls back, just records component history, eg. for lag compensation.
+    /// `ServerSnapshot`s aren't added, read history with [`TimewarpHistory`].
+    Server,
+}
+
 #[derive(Resource, Debug, Clone)]
 pub struct TimewarpConfig {
+    /// client or server, defaults to Client
+    pub mode: TimewarpMode,
     /// if you can update some entities one frame and some another, ie you don't receive
     /// entire-world update, set this to Oldest, or you will miss data.
     /// the default is Newest (for replicon, which is entire-world updates only atm)
@@ -23,12 +120,33 @@ pub struct TimewarpConfig {
     /// how many frames of old component values should we buffer?
     /// can't roll back any further than this. will depend on network lag and game mechanics.
     pub rollback_window: FrameNumber,
+    /// if set, `rollback_window` is adjusted at runtime to fit the observed snapshot age,
+    /// and existing buffers are resized to match.
+    pub adaptive_window: Option<AdaptiveRollbackWindow>,
     /// if set to true, a rollback will be initiated even if
     /// the stored predicted value matches the server snapshot.
     /// meant as a worst-case scenario for checking performance really.
     pub force_rollback_always: bool,
+    /// if set, periodically rolls back without any new data, to check the simulation is
+    /// deterministic.
+    pub determinism_check: Option<DeterminismCheck>,
+    /// if set to true, when rolling back, entities without a snapshot for the rollback frame are
+    /// rolled back further, to their last confirmed (snapshot) value, and re-predicted from there
+    /// instead of trusting our older predictions. Makes rollbacks deeper.
+    pub repredict_from_confirmed: bool,
+    /// if set to true, rollbacks only restore and resimulate entities in the [`RollbackIsland`]s
+    /// of the entities which triggered them. Entities without an island are always included.
+    pub partial_rollback: bool,
+    /// how many frames past an entity's last snapshot we'll extrapolate it to, for components
+    /// registered with `register_extrapolation`
+    pub max_extrapolation_age: FrameNumber,
+    /// what to do if asked to rollback further than `rollback_window` frames
+    pub overflow_policy: RollbackOverflowPolicy,
     /// schedule in which our `after_set` and rollback systems run, defaults to FixedUpdate
     pub schedule: Box<dyn ScheduleLabel>,
+    /// frames per second, used for the `FixedTime` period. If None, a `FixedTime` you inserted
+    /// yourself is left alone, otherwise it defaults to 60 fps.
+    pub tick_rate: Option<f32>,
     /// first set containing game logic
     pub first_set: BoxedSystemSet,
     /// last set containing game logic
@@ -37,32 +155,80 @@ pub struct TimewarpConfig {
 
 impl TimewarpConfig {
     /// Makes a new timewarp config, with defaults:
+    /// mode: Client
     /// rollback_window: 30
+    /// adaptive_window: None
     /// forced_rollback: false
+    /// determinism_check: None
+    /// repredict_from_confirmed: false
+    /// partial_rollback: false
+    /// max_extrapolation_age: 10
+    /// overflow_policy: Panic
     /// schedule: FixedUpdate
+    /// tick_rate: None
     pub fn new(first_set: impl SystemSet, last_set: impl SystemSet) -> Self {
         Self {
+            mode: TimewarpMode::Client,
             consolidation_strategy: RollbackConsolidationStrategy::Newest,
             first_set: Box::new(first_set),
             last_set: Box::new(last_set),
             // and defaults, override with builder fns:
             rollback_window: 30,
+            adaptive_window: None,
             force_rollback_always: false,
+            determinism_check: None,
+            repredict_from_confirmed: false,
+            partial_rollback: false,
+            max_extrapolation_age: 10,
+            overflow_policy: RollbackOverflowPolicy::Panic,
             schedule: Box::new(FixedUpdate),
+            tick_rate: None,
         }
     }
     pub fn with_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
         self.schedule = Box::new(schedule);
         self
     }
+    pub fn with_mode(mut self, mode: TimewarpMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    pub fn with_tick_rate(mut self, frames_per_second: f32) -> Self {
+        self.tick_rate = Some(frames_per_second);
+        self
+    }
     pub fn with_forced_rollback(mut self, enabled: bool) -> Self {
         self.force_rollback_always = enabled;
         self
     }
+    pub fn with_determinism_check(mut self, check: DeterminismCheck) -> Self {
+        self.determinism_check = Some(check);
+        self
+    }
+    pub fn with_repredict_from_confirmed(mut self, enabled: bool) -> Self {
+        self.repredict_from_confirmed = enabled;
+        self
+    }
+    pub fn with_partial_rollback(mut self, enabled: bool) -> Self {
+        self.partial_rollback = enabled;
+        self
+    }
+    pub fn with_max_extrapolation_age(mut self, num_frames: FrameNumber) -> Self {
+        self.max_extrapolation_age = num_frames;
+        self
+    }
+    pub fn with_overflow_policy(mut self, policy: RollbackOverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
     pub fn with_rollback_window(mut self, num_frames: FrameNumber) -> Self {
         self.rollback_window = num_frames;
         self
     }
+    pub fn with_adaptive_window(mut self, adaptive: AdaptiveRollbackWindow) -> Self {
+        self.adaptive_window = Some(adaptive);
+        self
+    }
     pub fn with_consolidation_strategy(mut self, strategy: RollbackConsolidationStrategy) -> Self {
         self.consolidation_strategy = strategy;
         self
@@ -77,18 +243,48 @@ impl TimewarpConfig {
     pub fn forced_rollback(&self) -> bool {
         self.force_rollback_always
     }
+    pub fn determinism_check(&self) -> Option<DeterminismCheck> {
+        self.determinism_check
+    }
+    pub fn repredict_from_confirmed(&self) -> bool {
+        self.repredict_from_confirmed
+    }
+    pub fn partial_rollback(&self) -> bool {
+        self.partial_rollback
+    }
+    pub fn max_extrapolation_age(&self) -> FrameNumber {
+        self.max_extrapolation_age
+    }
+    pub fn overflow_policy(&self) -> RollbackOverflowPolicy {
+        self.overflow_policy
+    }
+    pub fn mode(&self) -> TimewarpMode {
+        self.mode
+    }
+    pub fn tick_rate(&self) -> Option<f32> {
+        self.tick_rate
+    }
     pub fn schedule(&self) -> Box<dyn ScheduleLabel> {
         self.schedule.dyn_clone()
     }
     pub fn rollback_window(&self) -> FrameNumber {
         self.rollback_window
     }
+    pub fn adaptive_window(&self) -> Option<AdaptiveRollbackWindow> {
+        self.adaptive_window
+    }
     pub fn consolidation_strategy(&self) -> RollbackConsolidationStrategy {
         self.consolidation_strategy
     }
     pub fn set_consolidation_strategy(&mut self, strategy: RollbackConsolidationStrategy) {
         self.consolidation_strategy = strategy;
     }
+    /// the oldest frame a rollback ending at `end_frame` can start resimulating from.
+    /// we load component values for the frame before the start, so that has to be in the
+    /// ComponentHistory buffer too.
+    pub fn oldest_rollback_start(&self, end_frame: FrameNumber) -> FrameNumber {
+        (end_frame + 2).saturating_sub(self.rollback_window)
+    }
     pub fn is_within_rollback_window(
         &self,
         current_frame: FrameNumber,
@@ -99,11 +295,13 @@ impl TimewarpConfig {
 }
 
 /// Updated whenever we perform a rollback
-#[derive(Resource, Debug, Default)]
+#[derive(Resource, Debug, Default, Clone)]
 pub struct RollbackStats {
     pub num_rollbacks: u64,
     pub range_faults: u64,
     pub non_rollback_updates: u64,
+    /// rollbacks requested further back than the rollback window, see `RollbackOverflowPolicy`
+    pub rollback_overflows: u64,
 }
 
 /// If this resource exists, we are doing a rollback. Insert it to initate one manually.
@@ -121,6 +319,16 @@ pub struct Rollback {
     /// we preserve the original FixedUpdate period here and restore after rollback completes.
     /// (during rollback, we set the FixedUpdate period to 0.0, to effect fast-forward resimulation)
     pub original_period: Option<Duration>,
+    /// for partial rollbacks, the [`RollbackIsland`]s being resimulated. None means everything.
+    pub islands: Option<HashSet<u32>>,
+    /// entities whose new data caused this rollback
+    pub triggered_by: Vec<Entity>,
+    /// type names of the components whose new data caused this rollback
+    pub component_types: Vec<&'static str>,
+    /// when the rollback started, for measuring how long it took
+    pub started_at: Option<Instant>,
+    /// only requested by the [`DeterminismCheck`], so resimulated values should be unchanged
+    pub determinism_check: bool,
 }
 impl Rollback {
     /// `end` is the last frame to be resimulated
@@ -134,6 +342,39 @@ impl Rollback {
                 end: last_frame_to_resimulate,
             },
             original_period: None,
+            islands: None,
+            triggered_by: Vec::new(),
+            component_types: Vec::new(),
+            started_at: None,
+            determinism_check: false,
+        }
+    }
+    /// record what caused the rollback, from the requests that were consolidated into it.
+    pub fn with_triggers(mut self, requests: &[RollbackRequest]) -> Self {
+        self.triggered_by = requests.iter().filter_map(|ev| ev.entity()).collect();
+        self.triggered_by.sort();
+        self.triggered_by.dedup();
+        self.component_types = requests.iter().filter_map(|ev| ev.component()).collect();
+        self.component_types.sort();
+        self.component_types.dedup();
+        self.determinism_check =
+            !requests.is_empty() && requests.iter().all(|ev| ev.is_determinism_check());
+        self
+    }
+    /// how many frames are resimulated
+    pub fn depth(&self) -> FrameNumber {
+        self.range.end - self.range.start + 1
+    }
+    /// only restore and resimulate entities in these islands (and those without an island)
+    pub fn with_islands(mut self, islands: HashSet<u32>) -> Self {
+        self.islands = Some(islands);
+        self
+    }
+    /// is an entity with this island (or no island) being restored and resimulated?
+    pub fn affects(&self, island: Option<&RollbackIsland>) -> bool {
+        match (&self.islands, island) {
+            (Some(islands), Some(island)) => islands.contains(&island.0),
+            _ => true,
         }
     }
 }
@@ -141,18 +382,226 @@ impl Rollback {
 /// systems that want to initiate a rollback write one of these to
 /// the Events<RollbackRequest> queue.
 #[derive(Event, Debug)]
-pub struct RollbackRequest(FrameNumber);
+pub struct RollbackRequest {
+    frame: FrameNumber,
+    /// the entity whose new data caused the request, if any
+    entity: Option<Entity>,
+    /// type name of the component whose new data caused the request, if any
+    component: Option<&'static str>,
+    /// requested by the [`DeterminismCheck`], without any new data
+    determinism_check: bool,
+    /// the snapshot value that was too old to insert into the ComponentHistory, if any
+    rejected_snapshot: Option<RejectedSnapshot>,
+}
+
+/// A snapshot value that was too old for the ComponentHistory, so the
+/// [`RollbackOverflowPolicy`] can write it at a frame that's still in the rollback window.
+pub(crate) struct RejectedSnapshot(Box<dyn FnOnce(&mut World, FrameNumber) + Send + Sync>);
+
+impl RejectedSnapshot {
+    /// writes the value into the ComponentHistory at `frame`
+    pub(crate) fn apply(self, world: &mut World, frame: FrameNumber) {
+        (self.0)(world, frame)
+    }
+}
+
+impl std::fmt::Debug for RejectedSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RejectedSnapshot")
+    }
+}
 
 impl RollbackRequest {
     pub fn resimulate_this_frame_onwards(frame: FrameNumber) -> Self {
         if frame == 0 {
             warn!("RollbackRequest(0)!");
         }
-        Self(frame)
+        Self {
+            frame,
+            entity: None,
+            component: None,
+            determinism_check: false,
+            rejected_snapshot: None,
+        }
+    }
+    /// resimulate from `frame` without any new data, to compare against the original values.
+    pub fn determinism_check(frame: FrameNumber) -> Self {
+        Self {
+            determinism_check: true,
+            ..Self::resimulate_this_frame_onwards(frame)
+        }
+    }
+    /// record which entity caused this request. Needed for partial rollbacks.
+    pub fn triggered_by(mut self, entity: Entity) -> Self {
+        self.entity = Some(entity);
+        self
+    }
+    /// record which component's new data caused this request.
+    pub fn for_component<T>(self) -> Self {
+        self.for_component_type(std::any::type_name::<T>())
+    }
+    pub fn for_component_type(mut self, type_name: &'static str) -> Self {
+        self.component = Some(type_name);
+        self
+    }
+    /// keep a snapshot value that was too old to insert, for the overflow policy to apply.
+    pub(crate) fn with_rejected_snapshot<T: TimewarpComponent>(
+        mut self,
+        entity: Entity,
+        value: T,
+    ) -> Self {
+        self.rejected_snapshot = Some(RejectedSnapshot(Box::new(move |world, frame| {
+            let Some(mut comp_hist) = world.get_mut::<ComponentHistory<T>>(entity) else {
+                return;
+            };
+            if let Err(err) = comp_hist.insert(frame, value, &entity) {
+                warn!("{err:?} {entity:?} couldn't apply rejected snapshot @ {frame}");
+            }
+        })));
+        self
+    }
+    pub(crate) fn take_rejected_snapshot(&mut self) -> Option<RejectedSnapshot> {
+        self.rejected_snapshot.take()
     }
     pub fn frame(&self) -> FrameNumber {
-        self.0
+        self.frame
+    }
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+    pub fn component(&self) -> Option<&'static str> {
+        self.component
+    }
+    pub fn is_determinism_check(&self) -> bool {
+        self.determinism_check
+    }
+}
+
+/// Frames of the [`SnapshotBatch`](crate::prelude::SnapshotBatch)es applied since rollback
+/// requests were last consolidated, and whether each was complete.
+///
+/// `consolidate_rollback_requests` uses this to pick a rollback frame, and clears it each tick.
+#[derive(Resource, Debug, Default)]
+pub struct SnapshotBatchFrames {
+    frames: Vec<(FrameNumber, SnapshotCompleteness)>,
+}
+
+impl SnapshotBatchFrames {
+    pub fn record(&mut self, frame: FrameNumber, completeness: SnapshotCompleteness) {
+        self.frames.push((frame, completeness));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
     }
+    /// the newest frame for which we received an entire-world snapshot
+    pub fn newest_complete_frame(&self) -> Option<FrameNumber> {
+        self.frames
+            .iter()
+            .filter(|(_, c)| *c == SnapshotCompleteness::Complete)
+            .map(|(f, _)| *f)
+            .max()
+    }
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+/// Sent when a rollback was requested further back than the rollback window, and the
+/// [`RollbackOverflowPolicy`] is `EmitEvent`.
+#[derive(Event, Debug, Clone)]
+pub struct RollbackOverflow {
+    /// the rollback that would have been needed
+    pub range: Range<FrameNumber>,
+    /// entities that requested it
+    pub entities: Vec<Entity>,
+}
+
+/// Entities whose components get snapped to their snapshot values this frame, for the `Snap`
+/// [`RollbackOverflowPolicy`]. Set by `consolidate_rollback_requests` each tick.
+#[derive(Resource, Debug, Default)]
+pub(crate) struct OverflowSnapEntities(pub(crate) Vec<Entity>);
+
+/// Maps old entity ids to new ones, for [`RemapTimewarpEntities`].
+/// Entities not in the map are left as they are.
+#[derive(Debug, Clone, Default)]
+pub struct TimewarpEntityMap(HashMap<Entity, Entity>);
+
+impl TimewarpEntityMap {
+    pub fn insert(&mut self, from: Entity, to: Entity) {
+        self.0.insert(from, to);
+    }
+    pub fn with(mut self, from: Entity, to: Entity) -> Self {
+        self.insert(from, to);
+        self
+    }
+    /// the new id for `entity`, or `entity` itself if it isn't remapped.
+    pub fn get(&self, entity: Entity) -> Entity {
+        self.0.get(&entity).copied().unwrap_or(entity)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Send this to rewrite entity references in components registered with
+/// `app.register_entity_mapping::<T>()`. The live component, and every value buffered in its
+/// ComponentHistory and ServerSnapshot are mapped.
+///
+/// eg, after a predicted entity was matched up with the server's entity.
+#[derive(Event, Debug, Clone)]
+pub struct RemapTimewarpEntities(pub TimewarpEntityMap);
+
+/// Sent when a rollback starts, before the first frame is resimulated.
+#[derive(Event, Debug, Clone)]
+pub struct RollbackStarted {
+    /// the frames being resimulated
+    pub range: Range<FrameNumber>,
+    /// how many frames are resimulated
+    pub depth: FrameNumber,
+    /// entities whose new data caused the rollback
+    pub triggered_by: Vec<Entity>,
+    /// type names of the components whose new data caused the rollback
+    pub component_types: Vec<&'static str>,
+}
+
+/// Sent after each frame is resimulated during a rollback.
+#[derive(Event, Debug, Clone)]
+pub struct FrameResimulated {
+    pub frame: FrameNumber,
+    /// the range of the rollback this frame is part of
+    pub range: Range<FrameNumber>,
+}
+
+/// Sent when a [`DeterminismCheck`] resimulated a different value than was originally recorded.
+/// Only the first divergence found by each check is reported.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct DeterminismCheckFailed {
+    /// the frames that were resimulated
+    pub range: Range<FrameNumber>,
+    /// the first frame that came out differently
+    pub frame: FrameNumber,
+    pub entity: Entity,
+    /// type name of the component that diverged
+    pub component_type: &'static str,
+}
+
+/// The first divergence found by the current determinism check.
+#[derive(Resource, Debug, Default)]
+pub(crate) struct FirstDivergence(pub Option<DeterminismCheckFailed>);
+
+/// Sent when a rollback completes, ie the last frame was resimulated.
+#[derive(Event, Debug, Clone)]
+pub struct RollbackCompleted {
+    /// the frames that were resimulated
+    pub range: Range<FrameNumber>,
+    /// how many frames were resimulated
+    pub depth: FrameNumber,
+    /// entities whose new data caused the rollback
+    pub triggered_by: Vec<Entity>,
+    /// type names of the components whose new data caused the rollback
+    pub component_types: Vec<&'static str>,
+    /// wall-clock time spent resimulating
+    pub duration: Duration,
 }
 
 /// Every time a rollback completes, before the `Rollback` resources is removed,
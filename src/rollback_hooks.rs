@@ -0,0 +1,133 @@
+//! Callbacks for components that can't simply be overwritten with a clone during rollback.
+//!
+//! For example a physics body whose engine-side handle needs re-syncing, or an audio emitter
+//! whose playback position needs adjusting. Each hook gets the entity's `EntityCommands`, and
+//! `commands()` on that for anything else.
+//!
+//! ```rust,ignore
+//! app.register_rollback_with_hooks::<RigidBody>(
+//!     RollbackHooks::default()
+//!         .on_restore(|body, restored, _frame, _entity| body.restore_from(restored))
+//!         .on_rebirth(|body, _frame, entity| {
+//!             entity.insert(PhysicsHandle::new(body));
+//!         })
+//!         .on_rekill(|_frame, entity| {
+//!             entity.remove::<PhysicsHandle>();
+//!         }),
+//! );
+//! ```
+use crate::prelude::*;
+use bevy::{ecs::system::EntityCommands, prelude::*};
+
+/// `(current, restored, frame, entity)`, replaces the default of setting `current = restored`.
+/// Use `set_if_neq` on `current` to avoid triggering change detection needlessly.
+pub type RestoreHook<T> =
+    Box<dyn Fn(&mut Mut<T>, &T, FrameNumber, &mut EntityCommands) + Send + Sync>;
+/// `(component, frame, entity)`
+pub type ComponentHook<T> = Box<dyn Fn(&T, FrameNumber, &mut EntityCommands) + Send + Sync>;
+/// `(frame, entity)`
+pub type RemovalHook = Box<dyn Fn(FrameNumber, &mut EntityCommands) + Send + Sync>;
+
+/// Per-component rollback hooks, see [`TimewarpTraits::register_rollback_with_hooks`].
+#[derive(Resource)]
+pub struct RollbackHooks<T: TimewarpComponent> {
+    restore: Option<RestoreHook<T>>,
+    rebirth: Option<ComponentHook<T>>,
+    rekill: Option<RemovalHook>,
+    record: Option<ComponentHook<T>>,
+}
+
+impl<T: TimewarpComponent> Default for RollbackHooks<T> {
+    fn default() -> Self {
+        Self {
+            restore: None,
+            rebirth: None,
+            rekill: None,
+            record: None,
+        }
+    }
+}
+
+impl<T: TimewarpComponent> RollbackHooks<T> {
+    /// Called instead of overwriting an existing component with an older value, when a rollback
+    /// starts or a snapshot is applied mid-rollback.
+    pub fn on_restore(
+        mut self,
+        hook: impl Fn(&mut Mut<T>, &T, FrameNumber, &mut EntityCommands) + Send + Sync + 'static,
+    ) -> Self {
+        self.restore = Some(Box::new(hook));
+        self
+    }
+    /// Called after a rollback inserts the component again, with the value it was inserted with.
+    pub fn on_rebirth(
+        mut self,
+        hook: impl Fn(&T, FrameNumber, &mut EntityCommands) + Send + Sync + 'static,
+    ) -> Self {
+        self.rebirth = Some(Box::new(hook));
+        self
+    }
+    /// Called after a rollback removes the component again.
+    pub fn on_rekill(
+        mut self,
+        hook: impl Fn(FrameNumber, &mut EntityCommands) + Send + Sync + 'static,
+    ) -> Self {
+        self.rekill = Some(Box::new(hook));
+        self
+    }
+    /// Called after the component's value is recorded into its `ComponentHistory`, each frame.
+    pub fn on_record(
+        mut self,
+        hook: impl Fn(&T, FrameNumber, &mut EntityCommands) + Send + Sync + 'static,
+    ) -> Self {
+        self.record = Some(Box::new(hook));
+        self
+    }
+}
+
+/// Calls the hooks if there are any, so systems can take an `Option<Res<RollbackHooks<T>>>`.
+pub(crate) trait RunRollbackHooks<T: TimewarpComponent> {
+    /// restores `current` to `restored`, via the hook if there is one.
+    fn restore(
+        &self,
+        current: &mut Mut<T>,
+        restored: &T,
+        frame: FrameNumber,
+        commands: &mut Commands,
+        entity: Entity,
+    );
+    fn rebirth(&self, comp: &T, frame: FrameNumber, commands: &mut Commands, entity: Entity);
+    fn rekill(&self, frame: FrameNumber, commands: &mut Commands, entity: Entity);
+    fn record(&self, comp: &T, frame: FrameNumber, commands: &mut Commands, entity: Entity);
+}
+
+impl<T: TimewarpComponent> RunRollbackHooks<T> for Option<Res<'_, RollbackHooks<T>>> {
+    fn restore(
+        &self,
+        current: &mut Mut<T>,
+        restored: &T,
+        frame: FrameNumber,
+        commands: &mut Commands,
+        entity: Entity,
+    ) {
+        match self.as_ref().and_then(|hooks| hooks.restore.as_ref()) {
+            Some(hook) => hook(current, restored, frame, &mut commands.entity(entity)),
+            // don't trigger change detection if it's unchanged
+            None => current.set_if_neq(restored.clone()),
+        }
+    }
+    fn rebirth(&self, comp: &T, frame: FrameNumber, commands: &mut Commands, entity: Entity) {
+        if let Some(hook) = self.as_ref().and_then(|hooks| hooks.rebirth.as_ref()) {
+            hook(comp, frame, &mut commands.entity(entity));
+        }
+    }
+    fn rekill(&self, frame: FrameNumber, commands: &mut Commands, entity: Entity) {
+        if let Some(hook) = self.as_ref().and_then(|hooks| hooks.rekill.as_ref()) {
+            hook(frame, &mut commands.entity(entity));
+        }
+    }
+    fn record(&self, comp: &T, frame: FrameNumber, commands: &mut Commands, entity: Entity) {
+        if let Some(hook) = self.as_ref().and_then(|hooks| hooks.record.as_ref()) {
+            hook(comp, frame, &mut commands.entity(entity));
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Run conditions for game systems that need to know what timewarp is up to.
+use crate::prelude::*;
+use bevy::prelude::*;
+
+/// true while resimulating frames during a rollback, ie the [`Rollback`] resource exists.
+pub fn in_rollback() -> impl FnMut(Option<Res<Rollback>>) -> bool + Clone {
+    |rb: Option<Res<Rollback>>| rb.is_some()
+}
+
+/// true when simulating frames normally, for the first time.
+pub fn not_in_rollback() -> impl FnMut(Option<Res<Rollback>>) -> bool + Clone {
+    |rb: Option<Res<Rollback>>| rb.is_none()
+}
+
+/// true for the first resimulated frame of a rollback.
+pub fn rollback_started() -> impl FnMut(Option<Res<Rollback>>) -> bool + Clone {
+    |rb: Option<Res<Rollback>>| rb.is_some_and(|rb| rb.is_added())
+}
+
+/// true for the first normal frame after a rollback completes.
+pub fn rollback_just_completed() -> impl FnMut(Option<Res<PreviousRollback>>) -> bool + Clone {
+    |prev: Option<Res<PreviousRollback>>| prev.is_some_and(|prev| prev.is_changed())
+}
+
+/// true while resimulating `frame` during a rollback.
+///
+/// Compares against the [`GameClock`], so use it on systems that run after it's been advanced
+/// for the frame being simulated.
+pub fn resimulating_frame(
+    frame: FrameNumber,
+) -> impl FnMut(Option<Res<Rollback>>, Res<GameClock>) -> bool + Clone {
+    resimulating_frame_for::<GameClock>(frame)
+}
+
+/// [`resimulating_frame`] for apps using their own [`FrameSource`].
+pub fn resimulating_frame_for<F: FrameSource>(
+    frame: FrameNumber,
+) -> impl FnMut(Option<Res<Rollback>>, Res<F>) -> bool + Clone {
+    move |rb: Option<Res<Rollback>>, source: Res<F>| rb.is_some() && source.frame() == frame
+}
+
+/// true during a partial rollback, ie when some entities have [`SkipResimulation`].
+///
+/// Systems that only make sense for a whole-world resimulation can skip these.
+pub fn in_partial_rollback() -> impl FnMut(Option<Res<Rollback>>) -> bool + Clone {
+    |rb: Option<Res<Rollback>>| rb.is_some_and(|rb| rb.islands.is_some())
+}
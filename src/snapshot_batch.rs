@@ -0,0 +1,156 @@
+/// A SnapshotBatch is everything the server told us about one frame, in one go.
+/// ie, "here is frame F, with these entities and these component values".
+///
+/// Applying a batch writes all the values into the `ServerSnapshot`s (adding the timewarp
+/// components where needed), and records whether the batch was complete or partial, so
+/// `consolidate_rollback_requests` can pick the right frame to rollback to without you having
+/// to choose a `RollbackConsolidationStrategy`.
+///
+/// ```rust,ignore
+/// let mut batch = SnapshotBatch::complete(update.frame);
+/// for (entity, pos) in update.positions() {
+///     batch.add_component(entity, pos);
+/// }
+/// commands.add(batch);
+/// ```
+use crate::prelude::*;
+use bevy::{
+    ecs::{system::Command, world::EntityMut},
+    prelude::*,
+};
+
+/// Does the batch contain every replicated entity, or just some of them?
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SnapshotCompleteness {
+    /// Contains the entire world. Supersedes anything we know about older frames.
+    Complete,
+    /// Only some entities were included, so older snapshots are still relevant.
+    Partial,
+}
+
+type InsertFn = Box<
+    dyn FnOnce(&mut EntityMut, FrameNumber) -> Result<InsertComponentResult, TimewarpError>
+        + Send
+        + Sync,
+>;
+
+pub struct SnapshotBatch {
+    frame: FrameNumber,
+    completeness: SnapshotCompleteness,
+    /// entity, component type name, and fn to insert it
+    inserts: Vec<(Entity, &'static str, InsertFn)>,
+}
+
+impl std::fmt::Debug for SnapshotBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SnapshotBatch{{frame:{}, {:?}, inserts:{}}}",
+            self.frame,
+            self.completeness,
+            self.inserts.len()
+        )
+    }
+}
+
+impl SnapshotBatch {
+    pub fn new(frame: FrameNumber, completeness: SnapshotCompleteness) -> Self {
+        Self {
+            frame,
+            completeness,
+            inserts: Vec::new(),
+        }
+    }
+    /// a batch containing the entire world at `frame`
+    pub fn complete(frame: FrameNumber) -> Self {
+        Self::new(frame, SnapshotCompleteness::Complete)
+    }
+    /// a batch containing only some entities at `frame`
+    pub fn partial(frame: FrameNumber) -> Self {
+        Self::new(frame, SnapshotCompleteness::Partial)
+    }
+    pub fn frame(&self) -> FrameNumber {
+        self.frame
+    }
+    pub fn completeness(&self) -> SnapshotCompleteness {
+        self.completeness
+    }
+    pub fn is_complete(&self) -> bool {
+        self.completeness == SnapshotCompleteness::Complete
+    }
+    /// number of component values in the batch
+    pub fn len(&self) -> usize {
+        self.inserts.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inserts.is_empty()
+    }
+    /// add the authoritative value of component T for this entity
+    pub fn add_component<T: TimewarpComponent>(&mut self, entity: Entity, component: T) {
+        self.inserts.push((
+            entity,
+            std::any::type_name::<T>(),
+            Box::new(move |entity_mut, frame| {
+                entity_mut.insert_component_at_frame(frame, &component)
+            }),
+        ));
+    }
+    /// builder version of `add_component`
+    pub fn with_component<T: TimewarpComponent>(mut self, entity: Entity, component: T) -> Self {
+        self.add_component(entity, component);
+        self
+    }
+
+    /// Writes every value into the `ServerSnapshot`s at once.
+    ///
+    /// Nothing is applied if any of the entities are missing, or the frame is already
+    /// outside the rollback window.
+    pub fn apply_to_world(self, world: &mut World) -> Result<(), TimewarpError> {
+        let game_clock = frame_in_world(world);
+        let window = world.resource::<TimewarpConfig>().rollback_window();
+        if game_clock.saturating_sub(self.frame) >= window {
+            world.resource_mut::<RollbackStats>().range_faults += 1;
+            return Err(TimewarpError::FrameTooOld);
+        }
+        if let Some((entity, _, _)) = self
+            .inserts
+            .iter()
+            .find(|(entity, _, _)| world.get_entity(*entity).is_none())
+        {
+            return Err(TimewarpError::EntityMissing(*entity));
+        }
+        trace!("Applying {self:?} @ {game_clock}");
+        let frame = self.frame;
+        for (entity, type_name, insert_fn) in self.inserts {
+            let mut entity_mut = world.entity_mut(entity);
+            match insert_fn(&mut entity_mut, frame)? {
+                // existing SS was updated, `apply_snapshots_and_maybe_rollback` will compare it
+                // with our prediction and request a rollback if needed.
+                InsertComponentResult::IntoExistingSnapshot => {}
+                // component didn't exist before, so there was no prediction to compare against.
+                InsertComponentResult::ComponentsAdded => {
+                    if frame < game_clock {
+                        world.send_event(
+                            RollbackRequest::resimulate_this_frame_onwards(frame + 1)
+                                .triggered_by(entity)
+                                .for_component_type(type_name),
+                        );
+                    }
+                }
+            }
+        }
+        world
+            .resource_mut::<SnapshotBatchFrames>()
+            .record(frame, self.completeness);
+        Ok(())
+    }
+}
+
+impl Command for SnapshotBatch {
+    fn apply(self, world: &mut World) {
+        let frame = self.frame;
+        if let Err(err) = self.apply_to_world(world) {
+            error!("{err:?} applying SnapshotBatch for frame {frame}");
+        }
+    }
+}
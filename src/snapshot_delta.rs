@@ -0,0 +1,115 @@
+/// Delta-compressed snapshots, so the server only sends what changed since the last frame a
+/// client acknowledged.
+///
+/// The server diffs current values against its `ComponentHistory` at the acknowledged frame,
+/// and the client reconstructs full values from the `ServerSnapshot` it already has for that
+/// frame, adding them to a [`SnapshotBatch`] for the new frame.
+///
+/// ```rust,ignore
+/// impl TimewarpDelta for Position {
+///     type Delta = Vec2;
+///     fn diff(&self, baseline: &Self) -> Option<Vec2> {
+///         (self.0 != baseline.0).then(|| self.0 - baseline.0)
+///     }
+///     fn apply_delta(baseline: &Self, delta: &Vec2) -> Self {
+///         Position(baseline.0 + *delta)
+///     }
+/// }
+///
+/// // server, per client:
+/// fn send_updates(history: TimewarpHistory<Position>, clients: Query<&Client>) {
+///     for client in clients.iter() {
+///         client.send(history.delta_since(client.acked_frame));
+///     }
+/// }
+///
+/// // client:
+/// fn receive_updates(world: &mut World) {
+///     let delta: SnapshotDelta<Position> = receive();
+///     let mut batch = SnapshotBatch::partial(delta.frame);
+///     delta.add_to_batch(world, &mut batch)?;
+///     batch.apply_to_world(world)?;
+/// }
+/// ```
+use crate::prelude::*;
+use bevy::prelude::*;
+
+/// A component that can be sent as the difference from an older value.
+pub trait TimewarpDelta: TimewarpComponent {
+    type Delta: Clone + PartialEq + std::fmt::Debug + Send + Sync + 'static;
+    /// what changed from `baseline` to `self`, or None if nothing did.
+    fn diff(&self, baseline: &Self) -> Option<Self::Delta>;
+    /// the value `delta` was diffed to, from `baseline`.
+    fn apply_delta(baseline: &Self, delta: &Self::Delta) -> Self;
+}
+
+/// One entity's value of T, relative to the baseline frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentDelta<T: TimewarpDelta> {
+    /// same as at the baseline frame
+    Unchanged,
+    /// changed since the baseline frame
+    Changed(T::Delta),
+    /// no baseline to diff against, eg. T was added after the baseline frame
+    Full(T),
+}
+
+/// Every entity's value of T at `frame`, relative to what it was at `baseline_frame`.
+/// Entities that don't have T at `frame` are not included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotDelta<T: TimewarpDelta> {
+    pub baseline_frame: FrameNumber,
+    pub frame: FrameNumber,
+    pub deltas: Vec<(Entity, ComponentDelta<T>)>,
+}
+
+impl<T: TimewarpDelta> SnapshotDelta<T> {
+    /// number of entities that aren't unchanged
+    pub fn num_changed(&self) -> usize {
+        self.deltas
+            .iter()
+            .filter(|(_, delta)| *delta != ComponentDelta::Unchanged)
+            .count()
+    }
+    /// Reconstructs full values from the `ServerSnapshot`s at `baseline_frame`, and adds them
+    /// to the batch. Nothing is added if any baseline is missing.
+    pub fn add_to_batch(
+        &self,
+        world: &World,
+        batch: &mut SnapshotBatch,
+    ) -> Result<(), TimewarpError> {
+        if batch.frame() != self.frame {
+            return Err(TimewarpError::FrameMismatch {
+                expected: batch.frame(),
+                got: self.frame,
+            });
+        }
+        let mut values = Vec::with_capacity(self.deltas.len());
+        for (entity, delta) in self.deltas.iter() {
+            let value = match delta {
+                ComponentDelta::Full(value) => value.clone(),
+                ComponentDelta::Unchanged => self.baseline(world, *entity)?.clone(),
+                ComponentDelta::Changed(delta) => {
+                    T::apply_delta(self.baseline(world, *entity)?, delta)
+                }
+            };
+            values.push((*entity, value));
+        }
+        for (entity, value) in values {
+            batch.add_component(entity, value);
+        }
+        Ok(())
+    }
+
+    fn baseline<'w>(&self, world: &'w World, entity: Entity) -> Result<&'w T, TimewarpError> {
+        world
+            .get_entity(entity)
+            .ok_or(TimewarpError::EntityMissing(entity))?
+            .get::<ServerSnapshot<T>>()
+            .and_then(|ss| ss.at_frame(self.baseline_frame))
+            .ok_or(TimewarpError::MissingBaseline {
+                entity,
+                frame: self.baseline_frame,
+            })
+    }
+}
@@ -1,6 +1,7 @@
 use crate::prelude::*;
 use bevy::prelude::*;
 
+pub(crate) mod hierarchy;
 pub(crate) mod postfix_components;
 pub(crate) mod postfix_in_rollback;
 pub(crate) mod postfix_last;
@@ -11,27 +12,35 @@ pub(crate) mod prefix_in_rollback;
 pub(crate) mod prefix_not_in_rollback;
 pub(crate) mod prefix_start_rollback;
 
-/// footgun protection - in case your clock ticking fn isn't running properly, this avoids
-/// timewarp rolling back if the clock won't advance, since that would be an infinite loop.
-pub(crate) fn sanity_check(
-    game_clock: Res<GameClock>,
+/// footgun protection - in case the clock isn't advancing properly, this avoids timewarp rolling
+/// back if the clock won't advance, since that would be an infinite loop.
+/// timewarp advances the clock itself, so also warns if something else is advancing it too.
+pub(crate) fn sanity_check<F: FrameSource>(
+    game_clock: Res<F>,
     opt_rb: Option<Res<Rollback>>,
     mut prev_frame: Local<u32>,
 ) {
-    if **game_clock == 0 && opt_rb.is_some() {
-        panic!(
-            "⛔️ GameClock is on 0, but timewarp wants to rollback. {game_clock:?} rb:{:?}",
-            opt_rb.unwrap().clone()
-        );
-    }
-    if let Some(rb) = opt_rb {
-        if *prev_frame == **game_clock
+    if let Some(ref rb) = opt_rb {
+        if game_clock.frame() == 0 {
+            panic!("⛔️ Frame is 0, but timewarp wants to rollback. {game_clock:?} rb:{rb:?}");
+        }
+        if *prev_frame == game_clock.frame()
             && (rb.range.start == *prev_frame && rb.range.end != *prev_frame)
         {
             panic!(
-            "⛔️ GameClock not advancing properly, and timewarp wants to rollback. {game_clock:?} rb:{rb:?}"
+            "⛔️ Frame not advancing properly, and timewarp wants to rollback. {game_clock:?} rb:{rb:?}"
             );
         }
     }
-    *prev_frame = **game_clock;
+    // each frame is one on from the last, except the first frame of a rollback
+    let rewound = opt_rb.is_some_and(|rb| rb.range.start == game_clock.frame());
+    if game_clock.frame() > *prev_frame + 1 && !rewound {
+        warn!(
+            "⚠️ Frame advanced from {} to {} in one frame. Timewarp advances it in \
+            TimewarpPrefixSet::AdvanceFrame, you don't need your own frame increment system.",
+            *prev_frame,
+            game_clock.frame()
+        );
+    }
+    *prev_frame = game_clock.frame();
 }
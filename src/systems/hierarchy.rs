@@ -0,0 +1,109 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+/*
+    Hierarchy Systems
+
+    `Parent` is mirrored into `TimewarpParent`, which is registered for rollback like any other
+    component. During rollback, we apply `TimewarpParent` back to the hierarchy.
+*/
+
+/// Postfix: copy the current `Parent` of timewarp entities into `TimewarpParent`, so it is
+/// recorded in the ComponentHistory.
+pub(crate) fn record_parent(
+    mut q: Query<
+        (Entity, &Parent, Option<&mut TimewarpParent>),
+        (
+            With<TimewarpStatus>,
+            Without<DespawnMarker>,
+            Without<NoRollback>,
+            Without<SkipResimulation>,
+        ),
+    >,
+    q_orphans: Query<
+        Entity,
+        (
+            With<TimewarpParent>,
+            Without<Parent>,
+            Without<NoRollback>,
+            Without<SkipResimulation>,
+        ),
+    >,
+    mut commands: Commands,
+) {
+    for (entity, parent, opt_tw_parent) in q.iter_mut() {
+        match opt_tw_parent {
+            Some(mut tw_parent) => {
+                if tw_parent.0 != parent.get() {
+                    tw_parent.0 = parent.get();
+                }
+            }
+            None => {
+                commands.entity(entity).insert(TimewarpParent(parent.get()));
+            }
+        }
+    }
+    for entity in q_orphans.iter() {
+        commands.entity(entity).remove::<TimewarpParent>();
+    }
+}
+
+/// Prefix, during rollback: once `TimewarpParent` has been rolled back, reborn, or set from a
+/// snapshot, make the real hierarchy match it.
+/// This includes detaching children that weren't attached yet at this frame, since the presence
+/// of `TimewarpParent` is rolled back like any other component's.
+pub(crate) fn apply_parent_during_rollback<F: FrameSource>(
+    q: Query<
+        (Entity, Option<&TimewarpParent>, Option<&Parent>),
+        (
+            With<ComponentHistory<TimewarpParent>>,
+            Without<NoRollback>,
+            Without<SkipResimulation>,
+        ),
+    >,
+    q_exists: Query<Entity>,
+    mut commands: Commands,
+    game_clock: Res<F>,
+) {
+    for (entity, opt_tw_parent, opt_parent) in q.iter() {
+        match (opt_tw_parent, opt_parent) {
+            (Some(tw_parent), Some(parent)) if tw_parent.0 == parent.get() => {}
+            (Some(tw_parent), _) => {
+                if q_exists.get(tw_parent.0).is_err() {
+                    warn!("{entity:?} can't reattach to missing parent {tw_parent:?} @ {game_clock:?}");
+                    continue;
+                }
+                debug!("Reattaching {entity:?} to {tw_parent:?} during rollback @ {game_clock:?}");
+                commands.entity(entity).set_parent(tw_parent.0);
+            }
+            (None, Some(_)) => {
+                debug!("Detaching {entity:?} during rollback @ {game_clock:?}");
+                commands.entity(entity).remove_parent();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// A [`DespawnMarker`] on a parent also marks its children (recursively), with the same frame.
+/// Children attached to an already-marked parent are marked too.
+pub(crate) fn propagate_despawn_marker_to_children(
+    q: Query<(&DespawnMarker, &Children), Or<(Added<DespawnMarker>, Changed<Children>)>>,
+    q_children: Query<(Option<&Children>, Option<&DespawnMarker>)>,
+    mut commands: Commands,
+) {
+    for (marker, children) in q.iter() {
+        let mut stack = children.iter().copied().collect::<Vec<_>>();
+        while let Some(child) = stack.pop() {
+            let Ok((opt_grandchildren, opt_marker)) = q_children.get(child) else {
+                continue;
+            };
+            if opt_marker.is_none() {
+                trace!("Propagating {marker:?} to child {child:?}");
+                commands.entity(child).insert(DespawnMarker(marker.0));
+            }
+            if let Some(grandchildren) = opt_grandchildren {
+                stack.extend(grandchildren.iter().copied());
+            }
+        }
+    }
+}
@@ -14,70 +14,142 @@ fn debug_type<T: TimewarpComponent>() -> bool {
 
 /// despawn marker means remove all useful components, pending actual despawn after
 /// ROLLBACK_WINDOW frames have elapsed.
-pub(crate) fn remove_components_from_despawning_entities<T: TimewarpComponent>(
+pub(crate) fn remove_components_from_despawning_entities<T: TimewarpComponent, F: FrameSource>(
     mut q: Query<
         (Entity, &mut ComponentHistory<T>, &DespawnMarker),
         (Added<DespawnMarker>, With<T>, Without<NoRollback>),
     >,
     mut commands: Commands,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
 ) {
     for (entity, mut ch, dsm) in q.iter_mut() {
         trace!(
             "doing despawn marker {dsm:?} component removal for {entity:?} / {:?}",
             std::any::type_name::<T>()
         );
-        // record_component_death looks at RemovedComponents and will catch this, and
-        // register the death (ie, comphist.report_death_at_frame)
         commands.entity(entity).remove::<T>();
         ch.report_death_at_frame(game_clock.frame());
     }
 }
 
+/// Records the death of components that are no longer present, however they were removed.
+/// Runs during rollback too, so removals by game logic in resimulated frames are recorded.
+/// (births are recorded by `record_component_history`)
+pub(crate) fn record_component_lifecycle<T: TimewarpComponent, F: FrameSource>(
+    mut q: Query<
+        (Entity, &mut ComponentHistory<T>),
+        (Without<T>, Without<NoRollback>, Without<SkipResimulation>),
+    >,
+    game_clock: Res<F>,
+) {
+    let frame = game_clock.frame();
+    for (entity, mut comp_hist) in q.iter_mut() {
+        comp_hist.present = false;
+        if comp_hist.alive_at_frame(frame) {
+            debug!(
+                "{entity:?} Component death @ {frame} {:?}",
+                comp_hist.type_name()
+            );
+            comp_hist.report_death_at_frame(frame);
+        }
+    }
+}
+
+/// During a determinism check, compare what we resimulated with the value originally recorded,
+/// before `record_component_history` overwrites it.
+pub(crate) fn check_determinism<T: TimewarpComponent, F: FrameSource>(
+    q: Query<(Entity, &T, &ComponentHistory<T>), (Without<NoRollback>, Without<SkipResimulation>)>,
+    rb: Res<Rollback>,
+    game_clock: Res<F>,
+    mut first_divergence: ResMut<FirstDivergence>,
+) {
+    if !rb.determinism_check {
+        return;
+    }
+    let frame = game_clock.frame();
+    if first_divergence
+        .0
+        .as_ref()
+        .is_some_and(|failed| failed.frame <= frame)
+    {
+        return;
+    }
+    for (entity, comp, comp_hist) in q.iter() {
+        let Some(recorded) = comp_hist.at_frame(frame) else {
+            continue;
+        };
+        if *recorded != *comp {
+            warn!(
+                "{entity:?} {} diverged @ {frame}: {recorded:?} -> {comp:?}",
+                comp_hist.type_name()
+            );
+            first_divergence.0 = Some(DeterminismCheckFailed {
+                range: rb.range.clone(),
+                frame,
+                entity,
+                component_type: std::any::type_name::<T>(),
+            });
+            return;
+        }
+    }
+}
+
 /// Write current value of component to the ComponentHistory buffer for this frame
-pub(crate) fn record_component_history<T: TimewarpComponent>(
+pub(crate) fn record_component_history<T: TimewarpComponent, F: FrameSource>(
     mut q: Query<
         (
             Entity,
             &T,
             &mut ComponentHistory<T>,
             Option<&mut TimewarpCorrection<T>>,
+            Option<&mut ChangedAfterRollback<T>>,
         ),
-        Without<NoRollback>,
+        (Without<NoRollback>, Without<SkipResimulation>),
     >,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
     mut commands: Commands,
     opt_rb: Option<Res<Rollback>>,
+    hooks: Option<Res<RollbackHooks<T>>>,
 ) {
-    for (entity, comp, mut comp_hist, opt_correction) in q.iter_mut() {
-        // if we're in rollback, and on the last frame, we're about to overwrite something.
-        // we need to preserve it an report a misprediction, if it differs from the new value.
+    let frame = game_clock.frame();
+    for (entity, comp, mut comp_hist, opt_correction, opt_changed) in q.iter_mut() {
+        // if we're in rollback, and on the last frame, we're about to overwrite the value we had
+        // before the rollback. report whether the rollback changed it.
+        let old_val = opt_rb
+            .as_ref()
+            .filter(|rb| rb.range.end == frame)
+            .map(|_| comp_hist.at_frame(frame));
+        if let Some(old_val) = old_val {
+            if old_val != Some(comp) {
+                if let Some(mut changed) = opt_changed {
+                    changed.frame = frame;
+                } else {
+                    commands
+                        .entity(entity)
+                        .insert(ChangedAfterRollback::<T>::new(frame));
+                }
+            }
+        }
+        // and preserve it to report a misprediction, if it differs from the new value.
+        // there may be no old value, which is normal in the case of spawning a new entity in the
+        // past, like a bullet. it was never simulated for the current frame yet.
         if comp_hist.correction_logging_enabled {
-            if let Some(ref rb) = opt_rb {
-                if rb.range.end == game_clock.frame() {
-                    if let Some(old_val) = comp_hist.at_frame(game_clock.frame()) {
-                        if *old_val != *comp {
-                            info!(
-                                "Generating Correction for {entity:?}", //old:{:?} new{:?}",
-                                                                        // old_val, comp
-                            );
-                            if let Some(mut correction) = opt_correction {
-                                correction.before = old_val.clone();
-                                correction.after = comp.clone();
-                                correction.frame = game_clock.frame();
-                            } else {
-                                commands.entity(entity).insert(TimewarpCorrection::<T> {
-                                    before: old_val.clone(),
-                                    after: comp.clone(),
-                                    frame: game_clock.frame(),
-                                });
-                            }
-                        }
+            if let Some(Some(old_val)) = old_val {
+                if *old_val != *comp {
+                    info!(
+                        "Generating Correction for {entity:?}", //old:{:?} new{:?}",
+                                                                // old_val, comp
+                    );
+                    if let Some(mut correction) = opt_correction {
+                        correction.before = old_val.clone();
+                        correction.after = comp.clone();
+                        correction.frame = frame;
                     } else {
-                        // trace!("End of rb range, but no existing comp to correct");
-                        // this is normal in the case of spawning a new entity in the past,
-                        // like a bullet. it was never simulated for the current frame yet, so
-                        // it's expected that there wasn't an existing comp history val to replace.
+                        commands.entity(entity).insert(TimewarpCorrection::<T> {
+                            before: old_val.clone(),
+                            after: comp.clone(),
+                            frame,
+                        });
                     }
                 }
             }
@@ -85,6 +157,7 @@ pub(crate) fn record_component_history<T: TimewarpComponent>(
         // if debug_type::<T>() {
         //     info!("Recording Position {entity:?} @ {game_clock:?}");
         // }
+        comp_hist.present = true;
         // the main point of this system is just to save the component value to the buffer:
         // insert() does some logging
         match comp_hist.insert(game_clock.frame(), comp.clone(), &entity) {
@@ -93,42 +166,91 @@ pub(crate) fn record_component_history<T: TimewarpComponent>(
                 warn!("{err:?} Inserted a too-old frame value in record_component_history @ {game_clock:?} {}", comp_hist.type_name());
             }
         }
+        hooks.record(comp, frame, &mut commands, entity);
     }
 }
 
 /// add the ComponentHistory<T> and ServerSnapshot<T> whenever an entity gets the T component.
 /// NB: you must have called `app.register_rollback::<T>()` for this to work.
-pub(crate) fn add_timewarp_components<T: TimewarpComponent, const CORRECTION_LOGGING: bool>(
+pub(crate) fn add_timewarp_components<
+    T: TimewarpComponent,
+    F: FrameSource,
+    const CORRECTION_LOGGING: bool,
+>(
     q: Query<(Entity, &T), (Added<T>, Without<NoRollback>, Without<ComponentHistory<T>>)>,
     mut commands: Commands,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
     timewarp_config: Res<TimewarpConfig>,
 ) {
     for (e, comp) in q.iter() {
-        // insert component value at this frame, since the system that records it won't run
-        // if a rollback is happening this frame. and if it does it just overwrites
-        let mut comp_history = ComponentHistory::<T>::with_capacity(
-            timewarp_config.rollback_window as usize,
-            game_clock.frame(),
-            comp.clone(),
+        commands
+            .entity(e)
+            .insert(new_timewarp_components::<T, CORRECTION_LOGGING>(
+                &e,
+                comp,
+                game_clock.frame(),
+                &timewarp_config,
+            ));
+    }
+}
+
+/// In server mode, entities that get T only need a ComponentHistory<T>.
+pub(crate) fn add_component_history<T: TimewarpComponent, F: FrameSource>(
+    q: Query<(Entity, &T), (Added<T>, Without<NoRollback>, Without<ComponentHistory<T>>)>,
+    mut commands: Commands,
+    game_clock: Res<F>,
+    timewarp_config: Res<TimewarpConfig>,
+) {
+    for (e, comp) in q.iter() {
+        commands.entity(e).insert(new_component_history::<T, false>(
             &e,
-        );
-        if CORRECTION_LOGGING {
-            comp_history.enable_correction_logging();
-        }
-        trace!(
-            "Adding ComponentHistory<> to {e:?} for {:?}\nInitial val @ {:?} = {:?}",
-            std::any::type_name::<T>(),
+            comp,
             game_clock.frame(),
-            comp.clone(),
-        );
-        commands.entity(e).insert((
-            TimewarpStatus::new(0),
-            comp_history,
-            // server snapshots are sent event n frames, so there are going to be lots of Nones in
-            // the sequence buffer. increase capacity accordingly.
-            // TODO compute based on snapshot send rate.
-            ServerSnapshot::<T>::with_capacity(timewarp_config.rollback_window as usize * 60), // TODO yuk
+            &timewarp_config,
         ));
     }
 }
+
+/// The timewarp components for an entity that just got T, born at `frame`.
+pub(crate) fn new_timewarp_components<T: TimewarpComponent, const CORRECTION_LOGGING: bool>(
+    e: &Entity,
+    comp: &T,
+    frame: FrameNumber,
+    timewarp_config: &TimewarpConfig,
+) -> (TimewarpStatus, ComponentHistory<T>, ServerSnapshot<T>) {
+    (
+        TimewarpStatus::new(0),
+        new_component_history::<T, CORRECTION_LOGGING>(e, comp, frame, timewarp_config),
+        // server snapshots are sent event n frames, so there are going to be lots of Nones in
+        // the sequence buffer. increase capacity accordingly.
+        // TODO compute based on snapshot send rate.
+        ServerSnapshot::<T>::with_capacity(timewarp_config.rollback_window as usize * 60), // TODO yuk
+    )
+}
+
+fn new_component_history<T: TimewarpComponent, const CORRECTION_LOGGING: bool>(
+    e: &Entity,
+    comp: &T,
+    frame: FrameNumber,
+    timewarp_config: &TimewarpConfig,
+) -> ComponentHistory<T> {
+    // insert component value at this frame, since the system that records it won't run
+    // if a rollback is happening this frame. and if it does it just overwrites
+    let mut comp_history = ComponentHistory::<T>::with_capacity(
+        timewarp_config.rollback_window as usize,
+        frame,
+        comp.clone(),
+        e,
+    );
+    if CORRECTION_LOGGING {
+        comp_history.enable_correction_logging();
+    }
+    comp_history.present = true;
+    trace!(
+        "Adding ComponentHistory<> to {e:?} for {:?}\nInitial val @ {:?} = {:?}",
+        std::any::type_name::<T>(),
+        frame,
+        comp.clone(),
+    );
+    comp_history
+}
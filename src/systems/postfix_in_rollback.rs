@@ -6,43 +6,69 @@ use bevy::prelude::*;
     NOTE: Timewarp Postfix Systems run AFTER physics.
 */
 
-/// wipes RemovedComponents<T> queue for component T.
-/// useful during rollback, because we don't react to removals that are part of resimulating.
-pub(crate) fn clear_removed_components_queue<T: Component>(
-    mut e: RemovedComponents<T>,
-    game_clock: Res<GameClock>,
+/// lets the game know each time a frame has been resimulated.
+pub(crate) fn send_frame_resimulated<F: FrameSource>(
+    rb: Res<Rollback>,
+    game_clock: Res<F>,
+    mut resimulated_ev: EventWriter<FrameResimulated>,
 ) {
-    if !e.is_empty() {
-        debug!(
-            "Clearing f:{:?} RemovedComponents<{}> during rollback: {:?}",
-            game_clock.frame(),
-            std::any::type_name::<T>(),
-            e.len()
-        );
+    resimulated_ev.send(FrameResimulated {
+        frame: game_clock.frame(),
+        range: rb.range.clone(),
+    });
+}
+
+/// At the end of a [`DeterminismCheck`], report the first divergence found, if any.
+pub(crate) fn report_determinism_check<F: FrameSource>(
+    rb: Res<Rollback>,
+    game_clock: Res<F>,
+    conf: Res<TimewarpConfig>,
+    mut first_divergence: ResMut<FirstDivergence>,
+    mut failed_ev: EventWriter<DeterminismCheckFailed>,
+) {
+    if !rb.determinism_check || rb.range.end != game_clock.frame() {
+        return;
     }
-    e.clear();
+    let Some(failed) = first_divergence.0.take() else {
+        debug!("Determinism check passed {rb:?}");
+        return;
+    };
+    if conf
+        .determinism_check()
+        .is_some_and(|check| check.panic_on_failure)
+    {
+        panic!("⛔️ Determinism check failed: {failed:?}");
+    }
+    error!("Determinism check failed: {failed:?}");
+    failed_ev.send(failed);
 }
 
-// during rollback, need to re-remove components that were inserted, based on stored lifetimes.
-pub(crate) fn rekill_components_during_rollback<T: TimewarpComponent>(
-    mut q: Query<(Entity, &ComponentHistory<T>), With<T>>,
-    game_clock: Res<GameClock>,
+// during rollback, DespawnMarkers already in place have to remove components again once
+// resimulation reaches the despawn frame, since they won't be Added again.
+pub(crate) fn rekill_components_during_rollback<T: TimewarpComponent, F: FrameSource>(
+    mut q: Query<
+        (Entity, &mut ComponentHistory<T>, &DespawnMarker),
+        (With<T>, Without<NoRollback>, Without<SkipResimulation>),
+    >,
+    game_clock: Res<F>,
     mut commands: Commands,
+    hooks: Option<Res<RollbackHooks<T>>>,
 ) {
     let target_frame = game_clock.frame();
-    for (entity, comp_history) in q.iter_mut() {
-        trace!(
-            "rekill check? {entity:?} CH {} alive_range: {:?}",
-            comp_history.type_name(),
-            comp_history.alive_ranges
-        );
-        if !comp_history.alive_at_frame(target_frame) {
-            debug!(
-                "Re-removing {entity:?} -> {:?} during rollback for {:?}",
-                std::any::type_name::<T>(),
-                target_frame
-            );
-            commands.entity(entity).remove::<T>();
+    for (entity, mut comp_history, dsm) in q.iter_mut() {
+        let Some(despawn_frame) = dsm.0 else {
+            continue;
+        };
+        if despawn_frame > target_frame {
+            continue;
         }
+        debug!(
+            "Re-removing {entity:?} -> {:?} during rollback for {:?} {dsm:?}",
+            std::any::type_name::<T>(),
+            target_frame
+        );
+        commands.entity(entity).remove::<T>();
+        comp_history.report_death_at_frame(target_frame);
+        hooks.rekill(target_frame, &mut commands, entity);
     }
 }
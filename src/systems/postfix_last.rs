@@ -6,12 +6,13 @@ use bevy::prelude::*;
     NOTE: Timewarp Postfix Systems run AFTER physics.
 */
 
-/// Once a [`DespawnMarker`] has been around for `rollback_frames`, do the actual despawn.
+/// Once a [`DespawnMarker`] has been around for `rollback_window` frames, do the actual despawn.
+/// Uses the current window, which may have changed since the marker was added.
 /// also for new DespawnMarkers that don't have a frame yet, add one.
-pub(crate) fn despawn_entities_with_elapsed_despawn_marker(
+pub(crate) fn despawn_entities_with_elapsed_despawn_marker<F: FrameSource>(
     mut q: Query<(Entity, &mut DespawnMarker)>,
     mut commands: Commands,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
     timewarp_config: Res<TimewarpConfig>,
 ) {
     for (entity, mut marker) in q.iter_mut() {
@@ -21,7 +22,7 @@ pub(crate) fn despawn_entities_with_elapsed_despawn_marker(
         }
         if (marker.0.expect("Despawn marker should have a frame!")
             + timewarp_config.rollback_window)
-            == game_clock.frame()
+            <= game_clock.frame()
         {
             trace!(
                 "💀 Doing actual despawn of {entity:?} at frame {:?}",
@@ -31,3 +32,90 @@ pub(crate) fn despawn_entities_with_elapsed_despawn_marker(
         }
     }
 }
+
+/// When using an [`AdaptiveRollbackWindow`], resize the rollback window to fit the age of the
+/// snapshots we've been receiving recently. Age is the current frame minus the newest
+/// `TimewarpStatus::last_snap_frame`, sampled every frame, so it keeps growing while no
+/// snapshots arrive.
+pub(crate) fn adapt_rollback_window<F: FrameSource>(
+    q: Query<&TimewarpStatus>,
+    game_clock: Res<F>,
+    mut timewarp_config: ResMut<TimewarpConfig>,
+    mut samples: Local<std::collections::VecDeque<FrameNumber>>,
+) {
+    let Some(adaptive) = timewarp_config.adaptive_window() else {
+        return;
+    };
+    let newest_snap_frame = q
+        .iter()
+        .map(|status| status.last_snap_frame())
+        .max()
+        .unwrap_or(0);
+    // nothing to measure until the first snapshot arrives
+    if newest_snap_frame > 0 {
+        samples.push_back(game_clock.frame().saturating_sub(newest_snap_frame));
+    }
+    while samples.len() > adaptive.sample_frames {
+        samples.pop_front();
+    }
+    let Some(max_age) = samples.iter().max() else {
+        return;
+    };
+    let window = adaptive.window_for_age(*max_age);
+    // only deref mutably if it changed, since buffers get resized on change detection
+    if window != timewarp_config.rollback_window() {
+        debug!(
+            "Adapting rollback window {} -> {window} @ {game_clock:?}",
+            timewarp_config.rollback_window()
+        );
+        timewarp_config.rollback_window = window;
+    }
+}
+
+/// Resizes existing ComponentHistory and ServerSnapshot buffers when the rollback window changes.
+pub(crate) fn resize_buffers_to_rollback_window<T: TimewarpComponent>(
+    mut q: Query<(&mut ComponentHistory<T>, Option<&mut ServerSnapshot<T>>)>,
+    timewarp_config: Res<TimewarpConfig>,
+) {
+    let window = timewarp_config.rollback_window() as usize;
+    for (mut ch, opt_ss) in q.iter_mut() {
+        if ch.values.capacity() != window {
+            ch.values.set_capacity(window);
+            ch.external_changes.set_capacity(window);
+            ch.alive_ranges.set_capacity(window);
+        }
+        // no snapshots in server mode
+        if let Some(mut ss) = opt_ss {
+            if ss.values.capacity() != window * 60 {
+                ss.values.set_capacity(window * 60);
+            }
+        }
+    }
+}
+
+/// In a P2P session, frames before the confirmed frame will never be rolled back to,
+/// so we don't need their values.
+pub(crate) fn discard_history_before_confirmed_frame<T: TimewarpComponent>(
+    mut q: Query<&mut ComponentHistory<T>>,
+    confirmed: Res<P2pConfirmedFrame>,
+) {
+    for mut ch in q.iter_mut() {
+        ch.forget_before(**confirmed);
+    }
+}
+
+/// Delivers the events sent this frame to consumers, skipping ones they already got when this
+/// frame was first simulated, and cancelling ones that weren't sent again.
+pub(crate) fn deliver_timewarp_events<E: TimewarpEvent, F: FrameSource>(
+    mut tw_events: ResMut<TimewarpEvents<E>>,
+    mut updates: EventWriter<TimewarpEventUpdate<E>>,
+    game_clock: Res<F>,
+    timewarp_config: Res<TimewarpConfig>,
+) {
+    tw_events.set_capacity(timewarp_config.rollback_window() as usize);
+    let batch = tw_events.flush(game_clock.frame());
+    if !batch.is_empty() {
+        trace!("Delivering {batch:?} @ {game_clock:?}");
+    }
+    updates.send_batch(batch);
+}
@@ -10,15 +10,18 @@ use bevy::prelude::*;
 
 /// Blueprint components stay wrapped up until their target frame, then we unwrap them
 /// so the assembly systems can decorate them with various other components at that frame.
-pub(crate) fn unwrap_blueprints_at_target_frame<T: Component + std::fmt::Debug + Clone>(
+pub(crate) fn unwrap_blueprints_at_target_frame<
+    T: Component + std::fmt::Debug + Clone,
+    F: FrameSource,
+>(
     q: Query<(Entity, &AssembleBlueprintAtFrame<T>)>,
     mut commands: Commands,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
     rb: Option<Res<Rollback>>,
 ) {
     for (e, abaf) in q.iter() {
         // yes, blueprints assembled 1 frame late on clients. see NOTES
-        if abaf.frame != **game_clock {
+        if abaf.frame != game_clock.frame() {
             // debug!("Not assembling, gc={game_clock:?} {abaf:?}");
             continue;
         }
@@ -17,20 +17,151 @@ pub(crate) fn enable_error_correction_for_new_component_histories<T: TimewarpCom
     }
 }
 
-/// when components are removed, we log the death frame
-pub(crate) fn record_component_death<T: TimewarpComponent>(
-    mut removed: RemovedComponents<T>,
-    mut q: Query<&mut ComponentHistory<T>, Without<NoRollback>>,
-    game_clock: Res<GameClock>,
+/// Components inserted or removed since the last frame was simulated were changed outside of
+/// the game simulation, so resimulating won't recreate them. Record them to replay during rollback.
+pub(crate) fn record_external_changes<
+    T: TimewarpComponent,
+    F: FrameSource,
+    const CORRECTION_LOGGING: bool,
+>(
+    mut q: Query<
+        (Entity, Option<&T>, &mut ComponentHistory<T>),
+        (Without<NoRollback>, Without<DespawnMarker>),
+    >,
+    q_new: Query<(Entity, &T), (Without<ComponentHistory<T>>, Without<NoRollback>)>,
+    game_clock: Res<F>,
+    timewarp_config: Res<TimewarpConfig>,
+    mut commands: Commands,
 ) {
-    for entity in &mut removed {
-        if let Ok(mut ct) = q.get_mut(entity) {
-            debug!(
-                "{entity:?} Component death @ {:?} {:?}",
-                game_clock.frame(),
-                std::any::type_name::<T>()
-            );
-            ct.report_death_at_frame(game_clock.frame());
+    let next_frame = game_clock.frame() + 1;
+    for (entity, opt_comp, mut ch) in q.iter_mut() {
+        match (opt_comp, ch.present) {
+            (Some(comp), false) => {
+                debug!("{entity:?} {} inserted before {next_frame}", ch.type_name());
+                ch.report_external_change(next_frame, ExternalChange::Inserted(comp.clone()));
+            }
+            (None, true) => {
+                debug!("{entity:?} {} removed before {next_frame}", ch.type_name());
+                ch.report_external_change(next_frame, ExternalChange::Removed);
+            }
+            _ => {}
+        }
+    }
+    // spawned since the last frame. (spawns during a frame get their timewarp components in postfix)
+    for (entity, comp) in q_new.iter() {
+        let (status, mut ch, ss) = super::postfix_components::new_timewarp_components::<
+            T,
+            CORRECTION_LOGGING,
+        >(&entity, comp, next_frame, &timewarp_config);
+        ch.report_external_change(next_frame, ExternalChange::Inserted(comp.clone()));
+        commands.entity(entity).insert((status, ch, ss));
+    }
+}
+
+/// `InsertComponentAtFrame`s for future frames (eg. remote inputs or spawns arriving early due
+/// to input delay) are held until the clock reaches their frame, then applied like one that
+/// arrived just in time. Runs during rollback too, for ones inserted while resimulating.
+pub(crate) fn unpack_future_icafs<
+    T: TimewarpComponent,
+    F: FrameSource,
+    const CORRECTION_LOGGING: bool,
+>(
+    mut q: Query<
+        (
+            Entity,
+            &InsertComponentAtFrame<T>,
+            Option<&mut ServerSnapshot<T>>,
+            Option<&mut ComponentHistory<T>>,
+            Option<&mut TimewarpStatus>,
+        ),
+        Without<NoRollback>,
+    >,
+    game_clock: Res<F>,
+    timewarp_config: Res<TimewarpConfig>,
+    mut commands: Commands,
+) {
+    let frame = game_clock.frame();
+    for (e, icaf, opt_ss, opt_ch, opt_twstatus) in q.iter_mut() {
+        if icaf.frame != frame {
+            continue;
+        }
+        debug!("{e:?} applying held {icaf:?}");
+        match (opt_ss, opt_ch) {
+            (Some(mut ss), Some(mut ch)) => {
+                if let Err(err) = ch.insert(frame, icaf.component.clone(), &e) {
+                    warn!("{err:?} {e:?} unpack_future_icafs({})", ch.type_name());
+                }
+                if let Err(err) = ss.insert(frame, icaf.component.clone()) {
+                    warn!("{err:?} {e:?} unpack_future_icafs({})", ss.type_name());
+                }
+            }
+            _ => {
+                let (_, ch, mut ss) = super::postfix_components::new_timewarp_components::<
+                    T,
+                    CORRECTION_LOGGING,
+                >(
+                    &e, &icaf.component, frame, &timewarp_config
+                );
+                if let Err(err) = ss.insert(frame, icaf.component.clone()) {
+                    warn!(
+                        "{err:?} {e:?} unpack_future_icafs({}) - skipping",
+                        ss.type_name()
+                    );
+                    commands.entity(e).remove::<InsertComponentAtFrame<T>>();
+                    continue;
+                }
+                commands.entity(e).insert((ch, ss));
+            }
+        }
+        if let Some(mut tw_status) = opt_twstatus {
+            tw_status.set_snapped_at(frame);
+        } else {
+            commands.entity(e).insert(TimewarpStatus::new(frame));
+        }
+        commands
+            .entity(e)
+            .insert(icaf.component.clone())
+            .remove::<InsertComponentAtFrame<T>>();
+    }
+}
+
+/// rewrite entity references in T, and all its buffered values, when entities are remapped.
+pub(crate) fn remap_entities<T: MapTimewarpEntities>(
+    mut events: EventReader<RemapTimewarpEntities>,
+    mut q: Query<(
+        Option<&mut T>,
+        &mut ComponentHistory<T>,
+        Option<&mut ServerSnapshot<T>>,
+    )>,
+) {
+    for RemapTimewarpEntities(map) in events.iter() {
+        if map.is_empty() {
+            continue;
+        }
+        debug!(
+            "Remapping entities in {} {map:?}",
+            std::any::type_name::<T>()
+        );
+        for (opt_comp, mut ch, opt_ss) in q.iter_mut() {
+            if let Some(mut comp) = opt_comp {
+                // only deref mutably if something changed, to avoid triggering change detection
+                let mut mapped = comp.clone();
+                mapped.map_timewarp_entities(map);
+                if mapped != *comp {
+                    *comp = mapped;
+                }
+            }
+            for val in ch.values.values_mut() {
+                val.map_timewarp_entities(map);
+            }
+            // no snapshots in server mode
+            let Some(mut ss) = opt_ss else {
+                continue;
+            };
+            // not a new snapshot, so don't let apply_snapshots see this as Changed
+            for val in ss.bypass_change_detection().values.values_mut() {
+                val.map_timewarp_entities(map);
+            }
         }
     }
 }
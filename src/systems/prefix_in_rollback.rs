@@ -10,15 +10,20 @@ use bevy::prelude::*;
 
 /// If we reached the end of the Rollback range, restore the frame period and cleanup.
 /// this will remove the [`Rollback`] resource.
-pub(crate) fn check_for_rollback_completion(
-    game_clock: Res<GameClock>,
+pub(crate) fn check_for_rollback_completion<F: FrameSource>(
+    game_clock: Res<F>,
     rb: Res<Rollback>,
     mut commands: Commands,
     mut fx: ResMut<FixedTime>,
+    q_skipped: Query<Entity, With<SkipResimulation>>,
+    mut completed_ev: EventWriter<RollbackCompleted>,
 ) {
-    if rb.range.end != **game_clock {
+    if rb.range.end != game_clock.frame() {
         return;
     }
+    for entity in q_skipped.iter() {
+        commands.entity(entity).remove::<SkipResimulation>();
+    }
     // we keep track of the previous rollback mainly for integration tests
     commands.insert_resource(PreviousRollback(rb.as_ref().clone()));
     info!(
@@ -27,47 +32,126 @@ pub(crate) fn check_for_rollback_completion(
         rb.range.end - rb.range.start
     );
     fx.period = rb.original_period.unwrap();
+    completed_ev.send(RollbackCompleted {
+        range: rb.range.clone(),
+        depth: rb.depth(),
+        triggered_by: rb.triggered_by.clone(),
+        component_types: rb.component_types.clone(),
+        duration: rb
+            .started_at
+            .map(|started_at| started_at.elapsed())
+            .unwrap_or_default(),
+    });
     commands.remove_resource::<Rollback>();
 }
 
-/// during rollback, need to re-insert components that were removed, based on stored lifetimes.
-pub(crate) fn rebirth_components_during_rollback<T: TimewarpComponent>(
-    q: Query<(Entity, &ComponentHistory<T>), Without<T>>,
-    game_clock: Res<GameClock>,
+/// during rollback, components the server says existed at this frame are inserted if missing.
+/// (any other births are recorded as resimulation recreates them)
+pub(crate) fn rebirth_components_during_rollback<T: TimewarpComponent, F: FrameSource>(
+    mut q: Query<
+        (
+            Entity,
+            &ServerSnapshot<T>,
+            &mut ComponentHistory<T>,
+            Option<&DespawnMarker>,
+        ),
+        (Without<T>, Without<NoRollback>, Without<SkipResimulation>),
+    >,
+    game_clock: Res<F>,
     mut commands: Commands,
-    rb: Res<Rollback>,
+    hooks: Option<Res<RollbackHooks<T>>>,
+) {
+    let target_frame = game_clock.frame();
+    for (entity, ss, mut comp_history, opt_dsm) in q.iter_mut() {
+        let Some(comp_val) = ss.at_frame(target_frame) else {
+            continue;
+        };
+        if opt_dsm.is_some_and(|dsm| dsm.0.is_some_and(|f| f <= target_frame)) {
+            continue;
+        }
+        debug!(
+            "Reinserting {entity:?} -> {:?} during rollback for {:?}\n{:?}",
+            std::any::type_name::<T>(),
+            target_frame,
+            comp_val
+        );
+        if let Err(err) = comp_history.insert(target_frame, comp_val.clone(), &entity) {
+            warn!(
+                "{err:?} reinserting {} @ {target_frame}",
+                comp_history.type_name()
+            );
+        }
+        commands.entity(entity).insert(comp_val.clone());
+        hooks.rebirth(comp_val, target_frame, &mut commands, entity);
+    }
+}
+
+/// during rollback, components inserted or removed between frames are inserted or removed again
+/// before resimulating the frame that followed.
+pub(crate) fn replay_external_changes_during_rollback<T: TimewarpComponent, F: FrameSource>(
+    q: Query<
+        (Entity, Option<&T>, &ComponentHistory<T>),
+        (Without<NoRollback>, Without<SkipResimulation>),
+    >,
+    game_clock: Res<F>,
+    mut commands: Commands,
+    hooks: Option<Res<RollbackHooks<T>>>,
 ) {
-    for (entity, comp_history) in q.iter() {
-        let target_frame = game_clock.frame();
-        if comp_history.alive_at_frame(target_frame) {
-            // we could go fishing in SS for this, but it should be here if its alive.
-            // i think i'm only hitting this with rollback underflows though, during load?
-            // need more investigation and to figure out a test case..
-            let comp_val = comp_history.at_frame(target_frame).unwrap_or_else(|| {
-                error!(
-                    // gaps in CH values, can't rb to a gap?
-                    "{entity:?} no comp history for {:?} for {:?} focc:{:?} {game_clock:?} {rb:?}",
-                    target_frame,
-                    std::any::type_name::<T>(),
-                    comp_history.values.frame_occupancy(),
+    let next_frame = game_clock.frame() + 1;
+    for (entity, opt_comp, comp_history) in q.iter() {
+        match (comp_history.external_changes.get(next_frame), opt_comp) {
+            (Some(ExternalChange::Inserted(comp_val)), None) => {
+                debug!(
+                    "Reinserting {entity:?} -> {} before {next_frame} during rollback",
+                    comp_history.type_name()
                 );
-                error!("alive_ranges: {:?}", comp_history.alive_ranges);
-                panic!("death");
-            });
+                commands.entity(entity).insert(comp_val.clone());
+                hooks.rebirth(comp_val, next_frame, &mut commands, entity);
+            }
+            (Some(ExternalChange::Removed), Some(_)) => {
+                debug!(
+                    "Re-removing {entity:?} -> {} before {next_frame} during rollback",
+                    comp_history.type_name()
+                );
+                commands.entity(entity).remove::<T>();
+                hooks.rekill(next_frame, &mut commands, entity);
+            }
+            _ => {}
+        }
+    }
+}
 
-            debug!(
-                "Reinserting {entity:?} -> {:?} during rollback for {:?}\n{:?}",
-                std::any::type_name::<T>(),
-                target_frame,
-                comp_val
-            );
-            commands.entity(entity).insert(comp_val.clone());
-        } else {
-            trace!(
-                "comp not alive at {game_clock:?} for {entity:?} {:?} {}",
-                comp_history.alive_ranges,
-                std::any::type_name::<T>(),
-            );
+/// during rollback, snapshot values for frames in the middle of the rollback range replace what
+/// we just resimulated, otherwise they'd be lost when rolling back to an older frame than them.
+///
+/// eg. partial snapshots for frames 2 and 3 arrive at once, for different entities. We have to
+/// rollback to the oldest, but resimulating frame 3 would overwrite the value the server sent
+/// for it. Only frames we have a snapshot for are touched, and equal values are left alone, so
+/// entities without new data resimulate as before.
+pub(crate) fn apply_snapshots_during_rollback<T: TimewarpComponent, F: FrameSource>(
+    mut q: Query<
+        (Entity, &mut T, &ServerSnapshot<T>, &mut ComponentHistory<T>),
+        (Without<NoRollback>, Without<SkipResimulation>),
+    >,
+    game_clock: Res<F>,
+    mut commands: Commands,
+    hooks: Option<Res<RollbackHooks<T>>>,
+) {
+    let frame = game_clock.frame();
+    for (entity, mut comp, ss, mut comp_hist) in q.iter_mut() {
+        let Some(snap_val) = ss.at_frame(frame) else {
+            continue;
+        };
+        if *comp == *snap_val {
+            continue;
+        }
+        trace!(
+            "{entity:?} applying snapshot during rollback @ {frame} {}",
+            comp_hist.type_name()
+        );
+        hooks.restore(&mut comp, snap_val, frame, &mut commands, entity);
+        if let Err(err) = comp_hist.insert(frame, snap_val.clone(), &entity) {
+            warn!("{err:?} {entity:?} apply_snapshots_during_rollback @ {frame}");
         }
     }
 }
@@ -8,26 +8,27 @@ use std::cmp::Ordering;
 
 */
 use crate::prelude::*;
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashSet};
 
 /// If a new snapshot was added to SS, we may need to initiate a rollback
-pub(crate) fn apply_snapshots_and_maybe_rollback<T: TimewarpComponent>(
+pub(crate) fn apply_snapshots_and_maybe_rollback<T: TimewarpComponent, F: FrameSource>(
     mut q: Query<
         (
             Entity,
+            Option<&mut T>,
             &ServerSnapshot<T>,
             &mut ComponentHistory<T>,
             &mut TimewarpStatus,
         ),
         Changed<ServerSnapshot<T>>, // this includes Added<>
     >,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
     mut rb_ev: ResMut<Events<RollbackRequest>>,
     config: Res<TimewarpConfig>,
     mut commands: Commands,
     mut rb_stats: ResMut<RollbackStats>,
 ) {
-    for (entity, server_snapshot, mut comp_hist, mut tw_status) in q.iter_mut() {
+    for (entity, opt_comp, server_snapshot, mut comp_hist, mut tw_status) in q.iter_mut() {
         let snap_frame = server_snapshot.values.newest_frame();
 
         if snap_frame == 0 {
@@ -44,9 +45,13 @@ pub(crate) fn apply_snapshots_and_maybe_rollback<T: TimewarpComponent>(
         // we're in preudpate, the game clock is about to be incremented.
         // so if the snap frame = current clock, we need it inserted right now without rolling back
         // in this case, we don't need to write to comp_hist either, it will happen normally at the end of the frame.
-        if snap_frame == **game_clock {
+        if snap_frame == game_clock.frame() {
             trace!("Inserting latecomer {entity:?} {comp_from_snapshot:?} @ {snap_frame}");
-            commands.entity(entity).insert(comp_from_snapshot.clone());
+            if let Some(mut comp) = opt_comp {
+                comp.set_if_neq(comp_from_snapshot.clone());
+            } else {
+                commands.entity(entity).insert(comp_from_snapshot.clone());
+            }
             rb_stats.non_rollback_updates += 1;
             continue;
         }
@@ -62,22 +67,29 @@ pub(crate) fn apply_snapshots_and_maybe_rollback<T: TimewarpComponent>(
         }
 
         // need to update comp_hist, since that's where it's loaded from if we rollback.
+        let mut rejected = None;
         match comp_hist.insert(snap_frame, comp_from_snapshot.clone(), &entity) {
             Ok(()) => (),
             Err(err) => {
                 rb_stats.range_faults += 1;
                 // probably FrameTooOld.
-                panic!(
-                    "{err:?} {entity:?} apply_snapshots_and_maybe_rollback({}) - skipping",
+                if config.overflow_policy() == RollbackOverflowPolicy::Panic {
+                    panic!(
+                        "{err:?} {entity:?} apply_snapshots_and_maybe_rollback({}) - skipping",
+                        comp_hist.type_name()
+                    );
+                }
+                // we can't rollback to this, the overflow policy decides what happens
+                // when we consolidate the rollback request.
+                warn!(
+                    "{err:?} {entity:?} apply_snapshots_and_maybe_rollback({}) snap_frame: {snap_frame}",
                     comp_hist.type_name()
                 );
-                // we can't rollback to this
-                // this is bad.
-                // continue;
+                rejected = Some(comp_from_snapshot.clone());
             }
         }
 
-        if snap_frame < **game_clock {
+        if snap_frame < game_clock.frame() {
             debug!(
                 "Triggering rollback due to snapshot. {entity:?} snap_frame: {snap_frame} {}",
                 comp_hist.type_name()
@@ -85,20 +97,101 @@ pub(crate) fn apply_snapshots_and_maybe_rollback<T: TimewarpComponent>(
 
             // data for frame 100 is the post-physics value at the server, so we need it to be
             // inserted in time for the client to simulate frame 101.
-            rb_ev.send(RollbackRequest::resimulate_this_frame_onwards(
-                snap_frame + 1,
-            ));
+            let request = RollbackRequest::resimulate_this_frame_onwards(snap_frame + 1)
+                .triggered_by(entity)
+                .for_component::<T>();
+            rb_ev.send(match rejected {
+                Some(value) => request.with_rejected_snapshot(entity, value),
+                None => request,
+            });
             tw_status.increment_rollback_triggers();
         }
     }
 }
 
+/// When new snapshots for T arrived, entities that weren't included get an estimated value for
+/// the newest snapshot frame, extrapolated from their last snapshot. If it differs from our
+/// prediction, we treat it like a snapshot and request a rollback.
+pub(crate) fn extrapolate_missing_snapshots<T: TimewarpExtrapolate, F: FrameSource>(
+    q_snapped: Query<&ServerSnapshot<T>, Changed<ServerSnapshot<T>>>,
+    mut q: Query<(
+        Entity,
+        Option<&mut T>,
+        &ServerSnapshot<T>,
+        &ServerSnapshot<T::Rate>,
+        &mut ComponentHistory<T>,
+        &mut TimewarpStatus,
+    )>,
+    game_clock: Res<F>,
+    mut rb_ev: ResMut<Events<RollbackRequest>>,
+    config: Res<TimewarpConfig>,
+) {
+    let Some(snap_frame) = q_snapped.iter().map(|ss| ss.values.newest_frame()).max() else {
+        return;
+    };
+    // can't rollback to the future
+    if snap_frame == 0 || snap_frame > game_clock.frame() {
+        return;
+    }
+    for (entity, opt_comp, ss, rate_ss, mut comp_hist, mut tw_status) in q.iter_mut() {
+        let last_snap_frame = ss.values.newest_frame();
+        if last_snap_frame == 0
+            || last_snap_frame >= snap_frame
+            || snap_frame - last_snap_frame > config.max_extrapolation_age()
+            || !comp_hist.alive_at_frame(snap_frame)
+        {
+            continue;
+        }
+        let (Some(last_known), Some(rate)) = (
+            ss.at_frame(last_snap_frame),
+            rate_ss.at_frame(last_snap_frame),
+        ) else {
+            continue;
+        };
+        let estimate = last_known.extrapolate(rate, snap_frame - last_snap_frame);
+        // same as apply_snapshots_and_maybe_rollback, but our estimate is only trusted if it
+        // differs enough from what we predicted.
+        if snap_frame == game_clock.frame() {
+            if let Some(mut comp) = opt_comp {
+                if estimate.diverged(&comp) {
+                    trace!("Extrapolated latecomer {entity:?} {estimate:?} @ {snap_frame}");
+                    *comp = estimate;
+                }
+            }
+            continue;
+        }
+        if comp_hist
+            .at_frame(snap_frame)
+            .is_some_and(|predicted| !estimate.diverged(predicted))
+        {
+            continue;
+        }
+        debug!(
+            "Extrapolated {entity:?} from {last_snap_frame} to {snap_frame}: {estimate:?}, requesting rollback"
+        );
+        if let Err(err) = comp_hist.insert(snap_frame, estimate, &entity) {
+            warn!(
+                "{err:?} {entity:?} extrapolate_missing_snapshots({})",
+                comp_hist.type_name()
+            );
+            continue;
+        }
+        tw_status.increment_rollback_triggers();
+        rb_ev.send(
+            RollbackRequest::resimulate_this_frame_onwards(snap_frame + 1)
+                .triggered_by(entity)
+                .for_component::<T>(),
+        );
+    }
+}
+
 /// Move ICAF data to the SS and add SS, because it's missing.
 ///
 /// if an ICAF was inserted, we may need to rollback.
 ///
 pub(crate) fn unpack_icafs_adding_tw_components<
     T: TimewarpComponent,
+    F: FrameSource,
     const CORRECTION_LOGGING: bool,
 >(
     mut q: Query<
@@ -116,10 +209,15 @@ pub(crate) fn unpack_icafs_adding_tw_components<
     >,
     mut commands: Commands,
     timewarp_config: Res<TimewarpConfig>,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
     mut rb_ev: ResMut<Events<RollbackRequest>>,
 ) {
     for (e, icaf, opt_twstatus) in q.iter_mut() {
+        // held until the frame arrives, see `unpack_future_icafs`
+        if icaf.frame > game_clock.frame() {
+            trace!("{e:?} holding {icaf:?} until its frame");
+            continue;
+        }
         // insert the timewarp components
         let mut ch = ComponentHistory::<T>::with_capacity(
             timewarp_config.rollback_window as usize,
@@ -171,18 +269,14 @@ pub(crate) fn unpack_icafs_adding_tw_components<
                     .entity(e)
                     .insert((ch, ss))
                     .remove::<InsertComponentAtFrame<T>>();
-                rb_ev.send(RollbackRequest::resimulate_this_frame_onwards(
-                    icaf.frame + 1,
-                ));
-            }
-            Ordering::Greater => {
-                // clients are supposed to be ahead, so we don't really expect to get updates for
-                // future frames. We'll store it but can't rollback to future.
-                commands
-                    .entity(e)
-                    .insert((ch, ss))
-                    .remove::<InsertComponentAtFrame<T>>();
+                rb_ev.send(
+                    RollbackRequest::resimulate_this_frame_onwards(icaf.frame + 1)
+                        .triggered_by(e)
+                        .for_component::<T>(),
+                );
             }
+            // future ICAFs were held above
+            Ordering::Greater => continue,
         }
     }
 }
@@ -193,6 +287,7 @@ pub(crate) fn unpack_icafs_adding_tw_components<
 ///
 pub(crate) fn unpack_icafs_into_tw_components<
     T: TimewarpComponent,
+    F: FrameSource,
     const CORRECTION_LOGGING: bool,
 >(
     mut q: Query<
@@ -206,10 +301,15 @@ pub(crate) fn unpack_icafs_into_tw_components<
         (Added<InsertComponentAtFrame<T>>, Without<NoRollback>),
     >,
     mut commands: Commands,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
     mut rb_ev: ResMut<Events<RollbackRequest>>,
 ) {
     for (e, icaf, mut ss, mut ch, mut tw_status) in q.iter_mut() {
+        // held until the frame arrives, see `unpack_future_icafs`
+        if icaf.frame > game_clock.frame() {
+            trace!("{e:?} holding {icaf:?} until its frame");
+            continue;
+        }
         ch.insert(icaf.frame, icaf.component.clone(), &e)
             .expect("Couldn't insert ICAF to CH");
         ss.insert(icaf.frame, icaf.component.clone())
@@ -234,20 +334,22 @@ pub(crate) fn unpack_icafs_into_tw_components<
                 );
                 tw_status.increment_rollback_triggers();
                 commands.entity(e).remove::<InsertComponentAtFrame<T>>();
-                rb_ev.send(RollbackRequest::resimulate_this_frame_onwards(
-                    icaf.frame + 1,
-                ));
-            }
-            Ordering::Greater => {
-                // clients are supposed to be ahead, so we don't really expect to get updates for
-                // future frames. We'll store it but can't rollback to future.
-                commands.entity(e).remove::<InsertComponentAtFrame<T>>();
+                rb_ev.send(
+                    RollbackRequest::resimulate_this_frame_onwards(icaf.frame + 1)
+                        .triggered_by(e)
+                        .for_component::<T>(),
+                );
             }
+            // future ICAFs were held above
+            Ordering::Greater => continue,
         }
     }
 }
 
-pub(crate) fn request_rollback_for_blueprints<T: Component + std::fmt::Debug + Clone>(
+pub(crate) fn request_rollback_for_blueprints<
+    T: Component + std::fmt::Debug + Clone,
+    F: FrameSource,
+>(
     mut q: Query<
         (
             Entity,
@@ -256,7 +358,7 @@ pub(crate) fn request_rollback_for_blueprints<T: Component + std::fmt::Debug + C
         ),
         Added<AssembleBlueprintAtFrame<T>>,
     >,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
     mut rb_ev: ResMut<Events<RollbackRequest>>,
     mut commands: Commands,
 ) {
@@ -265,7 +367,7 @@ pub(crate) fn request_rollback_for_blueprints<T: Component + std::fmt::Debug + C
         // if frames == match, we want it inserted this frame but not rolled back.
         // don't do this here, the blueprint unpacking fn does this even during rollback.
         // all we have to do is trigger a rollback, and it'll be unpacked for us.
-        if snap_frame < **game_clock {
+        if snap_frame < game_clock.frame() {
             debug!(
                 "{game_clock:?} {entity:?} Requesting rollback for blueprint with snap_frame:{snap_frame} - {abaf:?}"
             );
@@ -276,24 +378,39 @@ pub(crate) fn request_rollback_for_blueprints<T: Component + std::fmt::Debug + C
                 tws.increment_rollback_triggers();
                 commands.entity(entity).insert(tws);
             }
-            rb_ev.send(RollbackRequest::resimulate_this_frame_onwards(
-                snap_frame + 1,
-            ));
+            rb_ev.send(
+                RollbackRequest::resimulate_this_frame_onwards(snap_frame + 1)
+                    .triggered_by(entity)
+                    .for_component::<T>(),
+            );
         }
     }
 }
 
+/// the stats, events and snapping for rollbacks that don't fit in the rollback window
+#[derive(SystemParam)]
+pub(crate) struct OverflowReporting<'w> {
+    rb_stats: ResMut<'w, RollbackStats>,
+    events: EventWriter<'w, RollbackOverflow>,
+    snap_entities: ResMut<'w, OverflowSnapEntities>,
+}
+
 /// potentially-concurrent systems request rollbacks by writing a request
 /// to the Events<RollbackRequest>, which we drain and use the smallest
 /// frame that was requested - ie, covering all requested frames.
 ///
-pub(crate) fn consolidate_rollback_requests(
+pub(crate) fn consolidate_rollback_requests<F: FrameSource>(
     mut rb_events: ResMut<Events<RollbackRequest>>,
+    mut batch_frames: ResMut<SnapshotBatchFrames>,
     mut commands: Commands,
     conf: Res<TimewarpConfig>,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
+    q_islands: Query<&RollbackIsland>,
+    mut overflow: OverflowReporting,
 ) {
+    overflow.snap_entities.0.clear();
     if rb_events.is_empty() {
+        batch_frames.clear();
         return;
     }
     /*
@@ -313,22 +430,153 @@ pub(crate) fn consolidate_rollback_requests(
        included in the first packet (@95) but not in the second (@96).
 
        if've not really tested the second scenario yet, because replicon uses whole-world updates atm.
+
+       If updates arrived as SnapshotBatches, we know which were complete, so we don't need the
+       configured strategy: the newest complete batch supersedes anything older, and any partial
+       batches newer than that need the oldest of their frames resimulating.
     */
-    let mut rb_frame: FrameNumber = 0;
     // NB: a manually managed event queue, which we drain here
-    for ev in rb_events.drain() {
-        match conf.consolidation_strategy() {
-            RollbackConsolidationStrategy::Newest => {
-                if rb_frame == 0 || ev.frame() > rb_frame {
-                    rb_frame = ev.frame();
+    let mut requests = rb_events.drain().collect::<Vec<_>>();
+    // for partial rollbacks, we need the islands of every entity that requested one.
+    // if anything requested a rollback without being in an island, we do a full rollback.
+    let islands = if conf.partial_rollback() {
+        requests
+            .iter()
+            .map(|ev| {
+                ev.entity()
+                    .and_then(|entity| q_islands.get(entity).ok())
+                    .map(|island| island.0)
+            })
+            .collect::<Option<HashSet<u32>>>()
+    } else {
+        None
+    };
+    let rb_frame = if batch_frames.is_empty() {
+        let mut rb_frame: FrameNumber = 0;
+        for ev in requests.iter() {
+            match conf.consolidation_strategy() {
+                RollbackConsolidationStrategy::Newest => {
+                    if rb_frame == 0 || ev.frame() > rb_frame {
+                        rb_frame = ev.frame();
+                    }
                 }
+                RollbackConsolidationStrategy::Oldest => {
+                    if rb_frame == 0 || ev.frame() < rb_frame {
+                        rb_frame = ev.frame();
+                    }
+                }
+            }
+        }
+        Some(rb_frame)
+    } else {
+        // data for frame 100 is needed to simulate frame 101, hence the +1
+        let floor = batch_frames
+            .newest_complete_frame()
+            .map_or(0, |frame| frame + 1);
+        requests
+            .iter()
+            .map(|ev| ev.frame())
+            .filter(|frame| *frame >= floor)
+            .min()
+    };
+    batch_frames.clear();
+    match rb_frame {
+        // too far back for the rollback window. (the Panic policy panics in rollback_initiated)
+        Some(frame)
+            if frame < conf.oldest_rollback_start(game_clock.frame())
+                && conf.overflow_policy() != RollbackOverflowPolicy::Panic =>
+        {
+            overflow.rb_stats.rollback_overflows += 1;
+            let policy = conf.overflow_policy();
+            warn!(
+                "Rollback to {frame} exceeds rollback_window @ {game_clock:?}, policy: {policy:?}"
+            );
+            let entities = requests.iter().filter_map(|ev| ev.entity());
+            match policy {
+                RollbackOverflowPolicy::EmitEvent => overflow.events.send(RollbackOverflow {
+                    range: frame..game_clock.frame(),
+                    entities: entities.collect(),
+                }),
+                RollbackOverflowPolicy::Snap => overflow.snap_entities.0.extend(entities),
+                _ => {}
             }
-            RollbackConsolidationStrategy::Oldest => {
-                if rb_frame == 0 || ev.frame() < rb_frame {
-                    rb_frame = ev.frame();
+            if policy == RollbackOverflowPolicy::Clamp {
+                let start = conf.oldest_rollback_start(game_clock.frame());
+                // the snapshots we couldn't store are written at the frame we'll load from,
+                // so the rollback starts from the server's values rather than our predictions.
+                for rejected in requests
+                    .iter_mut()
+                    .filter_map(|ev| ev.take_rejected_snapshot())
+                {
+                    commands.add(move |world: &mut World| rejected.apply(world, start - 1));
                 }
+                let rb = Rollback::new(start, game_clock.frame()).with_triggers(&requests);
+                commands.insert_resource(match islands {
+                    Some(islands) => rb.with_islands(islands),
+                    None => rb,
+                });
+            }
+        }
+        Some(rb_frame) => {
+            let rb = Rollback::new(rb_frame, game_clock.frame()).with_triggers(&requests);
+            if let Some(islands) = islands {
+                debug!("Partial rollback of islands: {islands:?}");
+                commands.insert_resource(rb.with_islands(islands));
+            } else {
+                commands.insert_resource(rb);
             }
         }
+        None => {
+            // eg, an old partial snapshot mispredicted, but a newer complete one didn't.
+            debug!("All rollback requests superseded by a complete snapshot batch");
+        }
+    }
+}
+
+/// For the [`DeterminismCheck`], periodically request a rollback without any new data.
+/// (if other requests are consolidated with it, the check is skipped)
+// is_multiple_of needs rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+pub(crate) fn request_determinism_check<F: FrameSource>(
+    conf: Res<TimewarpConfig>,
+    game_clock: Res<F>,
+    mut rb_events: ResMut<Events<RollbackRequest>>,
+    mut last_checked: Local<FrameNumber>,
+) {
+    let Some(check) = conf.determinism_check() else {
+        return;
+    };
+    let frame = game_clock.frame();
+    // the clock is back on this frame once the check's rollback completes
+    if check.every_frames == 0
+        || frame % check.every_frames != 0
+        || frame <= check.depth
+        || frame == *last_checked
+    {
+        return;
+    }
+    *last_checked = frame;
+    let start = (frame + 1 - check.depth).max(conf.oldest_rollback_start(frame));
+    debug!("Requesting determinism check from {start} @ {game_clock:?}");
+    rb_events.send(RollbackRequest::determinism_check(start));
+}
+
+/// For the `Snap` [`RollbackOverflowPolicy`]: when we can't rollback far enough, the entities that
+/// requested the rollback have their components set to the newest snapshot value instead.
+pub(crate) fn snap_components_on_rollback_overflow<T: TimewarpComponent>(
+    snap_entities: Res<OverflowSnapEntities>,
+    mut q: Query<(&mut T, &ServerSnapshot<T>), Without<NoRollback>>,
+) {
+    for entity in snap_entities.0.iter() {
+        let Ok((mut comp, ss)) = q.get_mut(*entity) else {
+            continue;
+        };
+        let Some(snap_val) = ss.newest_snap_frame().and_then(|f| ss.at_frame(f)) else {
+            continue;
+        };
+        if *comp != *snap_val {
+            debug!("{entity:?} snapping to {snap_val:?} after rollback overflow");
+            *comp = snap_val.clone();
+        }
     }
-    commands.insert_resource(Rollback::new(rb_frame, game_clock.frame()));
 }
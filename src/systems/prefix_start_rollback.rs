@@ -1,5 +1,6 @@
 use crate::prelude::*;
 use bevy::prelude::*;
+use bevy::utils::Instant;
 use std::time::Duration;
 /*
     NOTE: Timewarp Prefix Systems run at the top of FixedUpdate:
@@ -14,18 +15,20 @@ use std::time::Duration;
 /// The start of the rollback
 /// we wind back the game_clock to the first frame of the rollback range, and set the fixed period
 /// to zero so frames don't require elapsed time to tick. (ie, fast forward mode)
-pub(crate) fn rollback_initiated(
-    mut game_clock: ResMut<GameClock>,
+pub(crate) fn rollback_initiated<F: FrameSource>(
+    mut game_clock: ResMut<F>,
     mut rb: ResMut<Rollback>,
     mut fx: ResMut<FixedTime>,
     mut rb_stats: ResMut<RollbackStats>,
     timewarp_config: Res<TimewarpConfig>,
+    mut started_ev: EventWriter<RollbackStarted>,
 ) {
     // if we're trying to roll back further than our configured rollback window,
     // all sorts of things will fail spectacularly, so i'm just going to panic for now.
     // i think the way to handle this is in the game, if you get an update from the past older
     // than the window that you can't afford to ignore, like a reliable spawn message, then
     // deal with it and don't tell timewarp.
+    // (automatic rollback requests can use a different `RollbackOverflowPolicy` instead)
     if rb.range.end - rb.range.start >= timewarp_config.rollback_window {
         panic!(
             "⚠️⚠️⚠️ Attempted to rollback further than rollback_window: {rb:?} @ {:?}",
@@ -34,8 +37,9 @@ pub(crate) fn rollback_initiated(
     }
     // save original period for restoration after rollback completion
     rb.original_period = Some(fx.period);
+    rb.started_at = Some(Instant::now());
     rb_stats.num_rollbacks += 1;
-    let depth = rb.range.end - rb.range.start + 1;
+    let depth = rb.depth();
     // we wind clock back 1 past first resim frame, so we can load in data for the frame prior
     // so we go into our first resim frame with components in the correct state.
     let reset_game_clock_to = rb.range.start.saturating_sub(1);
@@ -47,7 +51,68 @@ pub(crate) fn rollback_initiated(
     // since increment happens after the timewarp prefix sets, we set the clock to this value - 1,
     // knowing that it will immediately be incremented to the next frame we need to simulate.
     // (once we've loaded in historical component values)
-    game_clock.set(reset_game_clock_to);
+    game_clock.set_frame(reset_game_clock_to);
+    started_ev.send(RollbackStarted {
+        range: rb.range.clone(),
+        depth,
+        triggered_by: rb.triggered_by.clone(),
+        component_types: rb.component_types.clone(),
+    });
+}
+
+/// Runs if Rollback was only just Added.
+/// For partial rollbacks, marks entities in islands that aren't being resimulated.
+pub(crate) fn mark_entities_skipping_resimulation(
+    rb: Res<Rollback>,
+    q: Query<(Entity, &RollbackIsland)>,
+    mut commands: Commands,
+) {
+    if rb.islands.is_none() {
+        return;
+    }
+    for (entity, island) in q.iter() {
+        if !rb.affects(Some(island)) {
+            commands.entity(entity).insert(SkipResimulation);
+        }
+    }
+}
+
+/// Runs if Rollback was only just Added, before `rollback_initiated` winds back the clock.
+///
+/// If `repredict_from_confirmed` is enabled, any entity that doesn't have a snapshot for the
+/// frame we're about to load from is only trusted as far back as its last confirmed value,
+/// so we move the start of the rollback back to re-predict forward from there.
+/// (clamped so we don't exceed the rollback window)
+pub(crate) fn extend_rollback_to_confirmed_frame<T: TimewarpComponent>(
+    mut rb: ResMut<Rollback>,
+    q: Query<
+        (Entity, &ServerSnapshot<T>, Option<&RollbackIsland>),
+        (With<ComponentHistory<T>>, Without<NoRollback>),
+    >,
+    timewarp_config: Res<TimewarpConfig>,
+) {
+    if !timewarp_config.repredict_from_confirmed() {
+        return;
+    }
+    let min_start = timewarp_config.oldest_rollback_start(rb.range.end);
+    // snapshots newer than the original load frame are applied as resimulation reaches them
+    let load_frame = rb.range.start.saturating_sub(1);
+    for (entity, ss, opt_island) in q.iter() {
+        if !rb.affects(opt_island) || ss.at_frame(load_frame).is_some() {
+            continue;
+        }
+        let Some((confirmed_frame, _)) = ss.confirmed_at_or_before(load_frame) else {
+            continue;
+        };
+        let new_start = (confirmed_frame + 1).max(min_start);
+        if new_start < rb.range.start {
+            debug!(
+                "{entity:?} last confirmed {} @ {confirmed_frame}, extending {rb:?} to start at {new_start}",
+                ss.type_name()
+            );
+            rb.range.start = new_start;
+        }
+    }
 }
 
 // for clarity when rolling back components
@@ -64,23 +129,29 @@ enum Provenance {
 /// restore component values to what they were at that frame, so the next frame can be resimulated.
 ///
 /// Also has to handle situation where the component didn't exist then, or it did exist, but doesnt in the present.
-pub(crate) fn rollback_component<T: TimewarpComponent>(
+pub(crate) fn rollback_component<T: TimewarpComponent, F: FrameSource>(
     rb: Res<Rollback>,
     // T is None in case where component removed but ComponentHistory persists
     mut q: Query<
         (
             Entity,
             Option<&mut T>,
-            &ComponentHistory<T>,
+            &mut ComponentHistory<T>,
             &ServerSnapshot<T>,
+            Option<&RollbackIsland>,
         ),
         Without<NoRollback>,
     >,
     mut commands: Commands,
-    game_clock: Res<GameClock>,
+    game_clock: Res<F>,
+    hooks: Option<Res<RollbackHooks<T>>>,
 ) {
-    for (entity, opt_comp, ch, ss) in q.iter_mut() {
-        let rollback_frame = **game_clock;
+    for (entity, opt_comp, mut ch, ss, opt_island) in q.iter_mut() {
+        // partial rollbacks leave entities in other islands as they are
+        if !rb.affects(opt_island) {
+            continue;
+        }
+        let rollback_frame = game_clock.frame();
         let end_frame = rb.range.end;
 
         trace!("rollback_component {entity:?} {} rollback-frame:{rollback_frame} {game_clock:?} end_frame={end_frame} {rb:?}", ch.type_name());
@@ -113,6 +184,18 @@ pub(crate) fn rollback_component<T: TimewarpComponent>(
             ch.alive_ranges
         );
 
+        // births and deaths after the rollback frame are recorded again during resimulation.
+        // a snapshot value is recorded too, it may be a birth we never simulated.
+        ch.forget_lifecycle_after(rollback_frame);
+        if let Some(val) = ss.at_frame(rollback_frame) {
+            if let Err(err) = ch.insert(rollback_frame, val.clone(), &entity) {
+                warn!(
+                    "{err:?} recording snapshot @ {rollback_frame} {}",
+                    ch.type_name()
+                );
+            }
+        }
+
         match provenance {
             Provenance::DeadThenDead => {
                 trace!(
@@ -127,19 +210,20 @@ pub(crate) fn rollback_component<T: TimewarpComponent>(
                     ch.type_name()
                 );
                 commands.entity(entity).remove::<T>();
+                hooks.rekill(rollback_frame, &mut commands, entity);
             }
             Provenance::AliveThenAlive => {
                 trace!(
                     "{game_clock:?} rollback component {entity:?} {} {provenance:?} - REPLACE WITH {comp_at_rollback_frame:?}",
                     ch.type_name()
                 );
+                let comp_val = comp_at_rollback_frame.expect("Component should be alive here!");
                 if let Some(mut comp) = opt_comp {
-                    *comp = comp_at_rollback_frame.expect("Component should be alive here!");
+                    hooks.restore(&mut comp, &comp_val, rollback_frame, &mut commands, entity);
                 } else {
                     // during new spawns this happens. not a bug.
-                    commands
-                        .entity(entity)
-                        .insert(comp_at_rollback_frame.expect("Component should be alive here"));
+                    commands.entity(entity).insert(comp_val.clone());
+                    hooks.rebirth(&comp_val, rollback_frame, &mut commands, entity);
                 }
             }
             Provenance::AliveThenDead => {
@@ -147,9 +231,9 @@ pub(crate) fn rollback_component<T: TimewarpComponent>(
                     "{game_clock:?} rollback component {entity:?} {} {provenance:?} - INSERT {comp_at_rollback_frame:?}",
                     ch.type_name()
                 );
-                commands
-                    .entity(entity)
-                    .insert(comp_at_rollback_frame.expect("Component should be alive here!!"));
+                let comp_val = comp_at_rollback_frame.expect("Component should be alive here!!");
+                commands.entity(entity).insert(comp_val.clone());
+                hooks.rebirth(&comp_val, rollback_frame, &mut commands, entity);
             }
         }
     }
@@ -0,0 +1,133 @@
+/// Gameplay events that survive rollbacks.
+///
+/// Send events from your game logic with a [`TimewarpEventWriter<E>`], and timewarp records
+/// which frame they were sent on. When a rollback resimulates that frame, events that were
+/// already delivered aren't delivered again. Events that are no longer sent are cancelled,
+/// and new ones are delivered, so things like sound effects and particles don't double up.
+///
+/// Consumers that don't run during rollback read them as [`TimewarpEventUpdate<E>`]s.
+///
+/// ```rust,ignore
+/// app.add_timewarp_event::<BulletHit>();
+///
+/// fn bullet_hits(mut hits: TimewarpEventWriter<BulletHit>) {
+///     hits.send(BulletHit { .. });
+/// }
+///
+/// fn play_sounds(mut updates: EventReader<TimewarpEventUpdate<BulletHit>>) {
+///     for update in updates.iter() {
+///         match update {
+///             TimewarpEventUpdate::Emitted { event, .. } => play_hit_sound(event),
+///             TimewarpEventUpdate::Cancelled { event, .. } => stop_hit_sound(event),
+///         }
+///     }
+/// }
+/// ```
+use crate::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+/// Bounds for events used with [`TimewarpEvents`]
+pub trait TimewarpEvent: Clone + PartialEq + Send + Sync + std::fmt::Debug + 'static {}
+
+impl<E> TimewarpEvent for E where E: Clone + PartialEq + Send + Sync + std::fmt::Debug + 'static {}
+
+/// Records events sent per frame, so we know what consumers have already been told about
+/// when a frame is resimulated.
+#[derive(Resource, Debug)]
+pub struct TimewarpEvents<E: TimewarpEvent> {
+    /// events sent during the current frame, not yet delivered
+    pending: Vec<E>,
+    /// events delivered for each frame
+    emitted: FrameBuffer<Vec<E>>,
+}
+
+impl<E: TimewarpEvent> TimewarpEvents<E> {
+    pub fn with_capacity(len: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            emitted: FrameBuffer::with_capacity(len, "TimewarpEvents"),
+        }
+    }
+    /// events delivered for `frame`, if it's still buffered.
+    pub fn emitted_at(&self, frame: FrameNumber) -> Option<&Vec<E>> {
+        self.emitted.get(frame)
+    }
+    pub(crate) fn send(&mut self, event: E) {
+        self.pending.push(event);
+    }
+    /// true if sending `event` now would just repeat one already delivered for `frame`.
+    pub(crate) fn already_emitted(&self, frame: FrameNumber, event: &E) -> bool {
+        let Some(emitted) = self.emitted.get(frame) else {
+            return false;
+        };
+        let num_pending = self.pending.iter().filter(|e| *e == event).count();
+        let num_emitted = emitted.iter().filter(|e| *e == event).count();
+        num_pending < num_emitted
+    }
+    /// diffs this frame's events against what was delivered for the frame before, returning
+    /// the updates consumers need to see.
+    pub(crate) fn flush(&mut self, frame: FrameNumber) -> Vec<TimewarpEventUpdate<E>> {
+        let sent = std::mem::take(&mut self.pending);
+        let mut previously = self.emitted.get(frame).cloned().unwrap_or_default();
+        let mut updates = Vec::new();
+        for event in sent.iter() {
+            if let Some(index) = previously.iter().position(|e| e == event) {
+                // already delivered when this frame was first simulated
+                previously.swap_remove(index);
+            } else {
+                updates.push(TimewarpEventUpdate::Emitted {
+                    frame,
+                    event: event.clone(),
+                });
+            }
+        }
+        for event in previously {
+            updates.push(TimewarpEventUpdate::Cancelled { frame, event });
+        }
+        if let Err(err) = self.emitted.insert(frame, sent) {
+            warn!("{err:?} recording {} @ {frame}", std::any::type_name::<E>());
+        }
+        updates
+    }
+    pub(crate) fn set_capacity(&mut self, len: usize) {
+        if self.emitted.capacity() != len {
+            self.emitted.set_capacity(len);
+        }
+    }
+}
+
+/// What consumers of a timewarp event see, as a normal bevy event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimewarpEventUpdate<E: TimewarpEvent> {
+    /// sent for the first time for this frame
+    Emitted { frame: FrameNumber, event: E },
+    /// was delivered for this frame, but after a rollback it didn't happen after all
+    Cancelled { frame: FrameNumber, event: E },
+}
+
+impl<E: TimewarpEvent> Event for TimewarpEventUpdate<E> {}
+
+impl<E: TimewarpEvent> TimewarpEventUpdate<E> {
+    pub fn frame(&self) -> FrameNumber {
+        match self {
+            Self::Emitted { frame, .. } | Self::Cancelled { frame, .. } => *frame,
+        }
+    }
+    pub fn event(&self) -> &E {
+        match self {
+            Self::Emitted { event, .. } | Self::Cancelled { event, .. } => event,
+        }
+    }
+}
+
+/// Use in your game systems to send timewarp events, including during rollback.
+#[derive(SystemParam)]
+pub struct TimewarpEventWriter<'w, E: TimewarpEvent> {
+    events: ResMut<'w, TimewarpEvents<E>>,
+}
+
+impl<E: TimewarpEvent> TimewarpEventWriter<'_, E> {
+    pub fn send(&mut self, event: E) {
+        self.events.send(event);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::systems::*;
 use bevy::{ecs::world::EntityMut, prelude::*};
+use std::marker::PhantomData;
 
 use super::*;
 
@@ -18,28 +19,169 @@ where
     // Nothing to implement, since T already supports the other traits.
 }
 
+/// Like bevy's `MapEntities`, for registered components that store `Entity` ids.
+/// Register with `app.register_entity_mapping::<T>()`, then send [`RemapTimewarpEntities`]
+/// events to rewrite the ids in the component and its buffered history.
+///
+/// ```rust,ignore
+/// impl MapTimewarpEntities for Target {
+///     fn map_timewarp_entities(&mut self, map: &TimewarpEntityMap) {
+///         self.0 = map.get(self.0);
+///     }
+/// }
+/// ```
+pub trait MapTimewarpEntities: TimewarpComponent {
+    fn map_timewarp_entities(&mut self, map: &TimewarpEntityMap);
+}
+
+impl MapTimewarpEntities for TimewarpParent {
+    fn map_timewarp_entities(&mut self, map: &TimewarpEntityMap) {
+        self.0 = map.get(self.0);
+    }
+}
+
+/// Estimates a component's value some frames after its last snapshot, for entities missing
+/// from newer (partial) snapshots. Register with `app.register_extrapolation::<T>()`.
+///
+/// ```rust,ignore
+/// impl TimewarpExtrapolate for Position {
+///     type Rate = Velocity;
+///     fn extrapolate(&self, velocity: &Velocity, frames: FrameNumber) -> Self {
+///         Position(self.0 + velocity.0 * frames as f32 * TIMESTEP)
+///     }
+///     fn diverged(&self, predicted: &Self) -> bool {
+///         self.0.distance(predicted.0) > 0.5
+///     }
+/// }
+/// ```
+pub trait TimewarpExtrapolate: TimewarpComponent {
+    /// read from the same snapshot as the last known value. Can be `Self`.
+    type Rate: TimewarpComponent;
+    /// the estimated value `frames` after this one
+    fn extrapolate(&self, rate: &Self::Rate, frames: FrameNumber) -> Self;
+    /// is our prediction far enough from this estimate to be worth a rollback?
+    fn diverged(&self, predicted: &Self) -> bool {
+        self != predicted
+    }
+}
+
 /// trait for registering components with the rollback system.
 pub trait TimewarpTraits {
     /// register component for rollback
     fn register_rollback<T: TimewarpComponent>(&mut self) -> &mut Self;
     /// register component for rollback, and also update a TimewarpCorrection<T> component when snapping
     fn register_rollback_with_correction_logging<T: TimewarpComponent>(&mut self) -> &mut Self;
+    /// register component for rollback, calling the [`RollbackHooks`] to fix up engine-side state
+    fn register_rollback_with_hooks<T: TimewarpComponent>(
+        &mut self,
+        hooks: RollbackHooks<T>,
+    ) -> &mut Self;
     /// register component for rollback with additional options
     fn register_rollback_with_options<T: TimewarpComponent, const CORRECTION_LOGGING: bool>(
         &mut self,
     ) -> &mut Self;
     fn register_blueprint<T: TimewarpComponent>(&mut self) -> &mut Self;
+    /// rollback `Parent`/`Children` relationships of timewarp entities, via [`TimewarpParent`]
+    fn register_rollback_hierarchy(&mut self) -> &mut Self;
+    /// remap entity references in T when [`RemapTimewarpEntities`] events are sent.
+    /// T must also be registered for rollback.
+    fn register_entity_mapping<T: MapTimewarpEntities>(&mut self) -> &mut Self;
+    /// extrapolate T for entities missing from newer snapshots, up to
+    /// `TimewarpConfig::max_extrapolation_age` frames. T and `T::Rate` must also be registered
+    /// for rollback.
+    fn register_extrapolation<T: TimewarpExtrapolate>(&mut self) -> &mut Self;
+    /// add a rollback-aware event channel, see [`TimewarpEvents`]
+    fn add_timewarp_event<E: TimewarpEvent>(&mut self) -> &mut Self;
+    /// track predicted cosmetic effects of kind K, see [`PredictedEffects`]
+    fn add_predicted_effects<K: TimewarpEvent>(&mut self) -> &mut Self;
+}
+
+/// Registers with the systems reading your own [`FrameSource`], for apps using
+/// `TimewarpPlugin::<F>::with_frame_source`. Registering on the `App` directly uses [`GameClock`].
+///
+/// ```rust,ignore
+/// TimewarpApp::<NetTick>::new(&mut app).register_rollback::<Position>();
+/// ```
+pub struct TimewarpApp<'a, F: FrameSource> {
+    app: &'a mut App,
+    _frame_source: PhantomData<F>,
+}
+
+impl<'a, F: FrameSource> TimewarpApp<'a, F> {
+    pub fn new(app: &'a mut App) -> Self {
+        Self {
+            app,
+            _frame_source: PhantomData,
+        }
+    }
 }
 
 impl TimewarpTraits for App {
+    fn register_rollback<T: TimewarpComponent>(&mut self) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).register_rollback::<T>();
+        self
+    }
+    fn register_rollback_with_correction_logging<T: TimewarpComponent>(&mut self) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).register_rollback_with_correction_logging::<T>();
+        self
+    }
+    fn register_rollback_with_hooks<T: TimewarpComponent>(
+        &mut self,
+        hooks: RollbackHooks<T>,
+    ) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).register_rollback_with_hooks::<T>(hooks);
+        self
+    }
+    fn register_rollback_with_options<T: TimewarpComponent, const CORRECTION_LOGGING: bool>(
+        &mut self,
+    ) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self)
+            .register_rollback_with_options::<T, CORRECTION_LOGGING>();
+        self
+    }
+    fn register_blueprint<T: TimewarpComponent>(&mut self) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).register_blueprint::<T>();
+        self
+    }
+    fn register_rollback_hierarchy(&mut self) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).register_rollback_hierarchy();
+        self
+    }
+    fn register_entity_mapping<T: MapTimewarpEntities>(&mut self) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).register_entity_mapping::<T>();
+        self
+    }
+    fn register_extrapolation<T: TimewarpExtrapolate>(&mut self) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).register_extrapolation::<T>();
+        self
+    }
+    fn add_timewarp_event<E: TimewarpEvent>(&mut self) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).add_timewarp_event::<E>();
+        self
+    }
+    fn add_predicted_effects<K: TimewarpEvent>(&mut self) -> &mut Self {
+        TimewarpApp::<GameClock>::new(self).add_predicted_effects::<K>();
+        self
+    }
+}
+
+impl<F: FrameSource> TimewarpTraits for TimewarpApp<'_, F> {
     fn register_rollback<T: TimewarpComponent>(&mut self) -> &mut Self {
         self.register_rollback_with_options::<T, false>()
     }
     fn register_rollback_with_correction_logging<T: TimewarpComponent>(&mut self) -> &mut Self {
         self.register_rollback_with_options::<T, true>()
     }
+    fn register_rollback_with_hooks<T: TimewarpComponent>(
+        &mut self,
+        hooks: RollbackHooks<T>,
+    ) -> &mut Self {
+        self.app.insert_resource(hooks);
+        self.register_rollback_with_options::<T, false>()
+    }
     fn register_blueprint<T: TimewarpComponent>(&mut self) -> &mut Self {
         let config = self
+            .app
             .world
             .get_resource::<TimewarpConfig>()
             .expect("TimewarpConfig resource expected");
@@ -47,90 +189,240 @@ impl TimewarpTraits for App {
         // when we rollback, unpack anything wrapped up for this frame.
         // this handles the case where we are rolling back because of a wrapped blueprint, and
         // we hit the exact frame to unwrap it like this:
-        self.add_systems(
+        self.app.add_systems(
             schedule.clone(),
             //  this apply_deferred is a hack so Res<Rollback> is visible for debugging in this systeem
             (
                 apply_deferred,
-                prefix_blueprints::unwrap_blueprints_at_target_frame::<T>,
+                prefix_blueprints::unwrap_blueprints_at_target_frame::<T, F>,
             )
                 .in_set(TimewarpPrefixSet::UnwrapBlueprints),
         );
-        self.add_systems(
+        self.app.add_systems(
             schedule.clone(),
-            prefix_not_in_rollback::request_rollback_for_blueprints::<T>
-                .before(prefix_not_in_rollback::consolidate_rollback_requests)
+            prefix_not_in_rollback::request_rollback_for_blueprints::<T, F>
+                .before(prefix_not_in_rollback::consolidate_rollback_requests::<F>)
+                .in_set(TimewarpPrefixSet::NotInRollback),
+        );
+        self
+    }
+    fn register_entity_mapping<T: MapTimewarpEntities>(&mut self) -> &mut Self {
+        let config = self
+            .app
+            .world
+            .get_resource::<TimewarpConfig>()
+            .expect("TimewarpConfig resource expected");
+        let schedule = config.schedule();
+        self.app.add_systems(
+            schedule,
+            prefix_first::remap_entities::<T>.in_set(TimewarpPrefixSet::First),
+        );
+        self
+    }
+    fn register_extrapolation<T: TimewarpExtrapolate>(&mut self) -> &mut Self {
+        let config = self
+            .app
+            .world
+            .get_resource::<TimewarpConfig>()
+            .expect("TimewarpConfig resource expected");
+        if config.mode() == TimewarpMode::Server {
+            // nothing to extrapolate from without snapshots
+            return self;
+        }
+        let schedule = config.schedule();
+        self.app.add_systems(
+            schedule,
+            prefix_not_in_rollback::extrapolate_missing_snapshots::<T, F>
+                .after(prefix_not_in_rollback::apply_snapshots_and_maybe_rollback::<T, F>)
+                .before(prefix_not_in_rollback::consolidate_rollback_requests::<F>)
                 .in_set(TimewarpPrefixSet::NotInRollback),
-        )
+        );
+        self
+    }
+    fn add_timewarp_event<E: TimewarpEvent>(&mut self) -> &mut Self {
+        let config = self
+            .app
+            .world
+            .get_resource::<TimewarpConfig>()
+            .expect("TimewarpConfig resource expected");
+        let schedule = config.schedule();
+        let window_size = config.rollback_window() as usize;
+        self.app
+            .insert_resource(TimewarpEvents::<E>::with_capacity(window_size));
+        self.app.add_event::<TimewarpEventUpdate<E>>();
+        self.app.add_systems(
+            schedule,
+            postfix_last::deliver_timewarp_events::<E, F>.in_set(TimewarpPostfixSet::Last),
+        );
+        self
+    }
+    fn add_predicted_effects<K: TimewarpEvent>(&mut self) -> &mut Self {
+        self.add_timewarp_event::<PredictedEffect<K>>()
+    }
+    fn register_rollback_hierarchy(&mut self) -> &mut Self {
+        self.register_rollback::<TimewarpParent>();
+        self.register_entity_mapping::<TimewarpParent>();
+        let config = self
+            .app
+            .world
+            .get_resource::<TimewarpConfig>()
+            .expect("TimewarpConfig resource expected");
+        let schedule = config.schedule();
+        self.app.add_systems(
+            schedule.clone(),
+            (
+                apply_deferred,
+                hierarchy::apply_parent_during_rollback::<F>,
+                apply_deferred,
+            )
+                .chain()
+                .run_if(resource_exists::<Rollback>())
+                .in_set(TimewarpPrefixSet::Last),
+        );
+        self.app.add_systems(
+            schedule,
+            (hierarchy::record_parent, apply_deferred)
+                .chain()
+                .after(hierarchy::propagate_despawn_marker_to_children)
+                .in_set(TimewarpPostfixSet::First),
+        );
+        self
     }
     fn register_rollback_with_options<T: TimewarpComponent, const CORRECTION_LOGGING: bool>(
         &mut self,
     ) -> &mut Self {
         let config = self
+            .app
             .world
             .get_resource::<TimewarpConfig>()
             .expect("TimewarpConfig resource expected");
         let schedule = config.schedule();
 
+        if config.mode() == TimewarpMode::Server {
+            // no snapshots or rollbacks, just record history
+            self.app.add_systems(
+                schedule.clone(),
+                (
+                    postfix_components::remove_components_from_despawning_entities::<T, F>,
+                    postfix_components::record_component_history::<T, F>,
+                    postfix_components::record_component_lifecycle::<T, F>,
+                    postfix_components::add_component_history::<T, F>,
+                )
+                    .in_set(TimewarpPostfixSet::Components),
+            );
+            self.app.add_systems(
+                schedule.clone(),
+                postfix_last::resize_buffers_to_rollback_window::<T>
+                    .run_if(resource_changed::<TimewarpConfig>())
+                    .in_set(TimewarpPostfixSet::Last),
+            );
+            return self;
+        }
+
         /*
                Prefix Systems
         */
         if CORRECTION_LOGGING {
-            self.add_systems(
+            self.app.add_systems(
                 schedule.clone(),
                 prefix_first::enable_error_correction_for_new_component_histories::<T>
                     .in_set(TimewarpPrefixSet::First),
             );
         }
-        self.add_systems(
+        self.app.add_systems(
             schedule.clone(),
-            prefix_first::record_component_death::<T>
+            prefix_first::unpack_future_icafs::<T, F, CORRECTION_LOGGING>
+                .in_set(TimewarpPrefixSet::First),
+        );
+        self.app.add_systems(
+            schedule.clone(),
+            prefix_first::record_external_changes::<T, F, CORRECTION_LOGGING>
                 .run_if(not(resource_exists::<Rollback>()))
                 .in_set(TimewarpPrefixSet::First),
         );
-        self.add_systems(
+        self.app.add_systems(
             schedule.clone(),
-            (prefix_in_rollback::rebirth_components_during_rollback::<T>,)
+            (
+                prefix_in_rollback::replay_external_changes_during_rollback::<T, F>,
+                prefix_in_rollback::rebirth_components_during_rollback::<T, F>,
+                prefix_in_rollback::apply_snapshots_during_rollback::<T, F>,
+            )
                 .in_set(TimewarpPrefixSet::InRollback),
         );
         // this may result in a Rollback resource being inserted.
-        self.add_systems(
+        self.app.add_systems(
             schedule.clone(),
             (
-                prefix_not_in_rollback::unpack_icafs_into_tw_components::<T, CORRECTION_LOGGING>,
-                prefix_not_in_rollback::unpack_icafs_adding_tw_components::<T, CORRECTION_LOGGING>,
-                prefix_not_in_rollback::apply_snapshots_and_maybe_rollback::<T>,
+                prefix_not_in_rollback::unpack_icafs_into_tw_components::<T, F, CORRECTION_LOGGING>,
+                prefix_not_in_rollback::unpack_icafs_adding_tw_components::<T, F, CORRECTION_LOGGING>,
+                prefix_not_in_rollback::apply_snapshots_and_maybe_rollback::<T, F>,
             )
-                .before(prefix_not_in_rollback::consolidate_rollback_requests)
+                .before(prefix_not_in_rollback::consolidate_rollback_requests::<F>)
+                .in_set(TimewarpPrefixSet::NotInRollback),
+        );
+        self.app.add_systems(
+            schedule.clone(),
+            prefix_not_in_rollback::snap_components_on_rollback_overflow::<T>
+                .after(prefix_not_in_rollback::consolidate_rollback_requests::<F>)
                 .in_set(TimewarpPrefixSet::NotInRollback),
         );
-        self.add_systems(
+        self.app.add_systems(
+            schedule.clone(),
+            prefix_start_rollback::extend_rollback_to_confirmed_frame::<T>
+                .in_set(TimewarpPrefixSet::StartRollback)
+                .before(prefix_start_rollback::rollback_initiated::<F>),
+        );
+        self.app.add_systems(
             schedule.clone(),
-            (prefix_start_rollback::rollback_component::<T>,)
+            (prefix_start_rollback::rollback_component::<T, F>,)
                 .in_set(TimewarpPrefixSet::StartRollback)
-                .after(prefix_start_rollback::rollback_initiated),
+                .after(prefix_start_rollback::rollback_initiated::<F>),
         );
 
         /*
                Postfix Systems
         */
-        self.add_systems(
+        self.app.add_systems(
             schedule.clone(),
             (
-                postfix_components::remove_components_from_despawning_entities::<T>,
-                postfix_components::record_component_history::<T>,
-                postfix_components::add_timewarp_components::<T, CORRECTION_LOGGING>,
+                postfix_components::remove_components_from_despawning_entities::<T, F>,
+                postfix_components::check_determinism::<T, F>
+                    .before(postfix_components::record_component_history::<T, F>)
+                    .run_if(resource_exists::<Rollback>()),
+                postfix_components::record_component_history::<T, F>,
+                postfix_components::record_component_lifecycle::<T, F>,
+                postfix_components::add_timewarp_components::<T, F, CORRECTION_LOGGING>,
             )
                 .in_set(TimewarpPostfixSet::Components),
         );
-        self.add_systems(
+        self.app.add_systems(
             schedule.clone(),
-            (
-                postfix_in_rollback::rekill_components_during_rollback::<T>,
-                postfix_in_rollback::clear_removed_components_queue::<T>,
-            )
+            postfix_in_rollback::rekill_components_during_rollback::<T, F>
                 .in_set(TimewarpPostfixSet::InRollback),
-        )
+        );
+        self.app.add_systems(
+            schedule.clone(),
+            postfix_last::discard_history_before_confirmed_frame::<T>
+                .run_if(resource_exists_and_changed::<P2pConfirmedFrame>())
+                .in_set(TimewarpPostfixSet::Last),
+        );
+        #[cfg(feature = "debug_ui")]
+        self.app.add_systems(
+            schedule.clone(),
+            crate::debug_ui::collect_component_debug_info::<T>
+                .after(crate::debug_ui::begin_debug_frame::<F>)
+                .run_if(resource_exists::<TimewarpDebugInfo>())
+                .run_if(not_in_rollback())
+                .in_set(TimewarpPostfixSet::Last),
+        );
+        self.app.add_systems(
+            schedule.clone(),
+            postfix_last::resize_buffers_to_rollback_window::<T>
+                .after(postfix_last::adapt_rollback_window::<F>)
+                .run_if(resource_changed::<TimewarpConfig>())
+                .in_set(TimewarpPostfixSet::Last),
+        );
+        self
     }
 }
 
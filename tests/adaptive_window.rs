@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+fn window(app: &App) -> FrameNumber {
+    app.world.resource::<TimewarpConfig>().rollback_window()
+}
+
+/// inserts a snapshot `age` frames old, agreeing with our prediction if we still have it
+fn snapshot_with_age(app: &mut App, e: Entity, age: FrameNumber) {
+    let frame = app.world.resource::<GameClock>().frame() - age;
+    let val = app
+        .comp_val_at::<Enemy>(e, frame)
+        .cloned()
+        .unwrap_or(Enemy {
+            health: 1000 - frame as i32,
+        });
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e)
+        .unwrap()
+        .insert(frame, val)
+        .unwrap();
+}
+
+#[test]
+fn window_follows_snapshot_age() {
+    let mut app = setup_test_app();
+    {
+        let mut config = app.world.resource_mut::<TimewarpConfig>();
+        config.adaptive_window = Some(AdaptiveRollbackWindow::new(5, 40).with_sample_frames(10));
+        // snapshots older than the starting window can't be rolled back to
+        config.overflow_policy = RollbackOverflowPolicy::Drop;
+    }
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 1000 }).id();
+    for _ in 0..20 {
+        tick(&mut app);
+    }
+    assert_eq!(window(&app), TEST_ROLLBACK_WINDOW);
+
+    // snapshots arriving 14 frames late
+    for _ in 0..5 {
+        snapshot_with_age(&mut app, e1, 14);
+        tick(&mut app);
+    }
+    // applied before the clock advanced, so 15 frames old + 1 + margin of 2
+    assert_eq!(window(&app), 18);
+    let ch = app.world.get::<ComponentHistory<Enemy>>(e1).unwrap();
+    assert_eq!(ch.values.capacity(), 18);
+    let ss = app.world.get::<ServerSnapshot<Enemy>>(e1).unwrap();
+    assert_eq!(ss.values.capacity(), 18 * 60);
+
+    // once the bigger buffer fills up, late snapshots can be checked against our predictions
+    for _ in 0..20 {
+        snapshot_with_age(&mut app, e1, 14);
+        tick(&mut app);
+    }
+    let num_rollbacks = app.world.resource::<RollbackStats>().num_rollbacks;
+    snapshot_with_age(&mut app, e1, 14);
+    tick(&mut app);
+    assert_eq!(
+        app.world.resource::<RollbackStats>().num_rollbacks,
+        num_rollbacks
+    );
+
+    // snapshots arriving promptly again, old samples age out and the window shrinks to min
+    for _ in 0..15 {
+        snapshot_with_age(&mut app, e1, 1);
+        tick(&mut app);
+    }
+    assert_eq!(window(&app), 5);
+    let ch = app.world.get::<ComponentHistory<Enemy>>(e1).unwrap();
+    assert_eq!(ch.values.capacity(), 5);
+    assert_eq!(
+        ch.values.oldest_frame(),
+        app.world.resource::<GameClock>().frame() - 4
+    );
+}
+
+#[test]
+fn window_grows_while_snapshots_stop() {
+    let mut app = setup_test_app();
+    app.world.resource_mut::<TimewarpConfig>().adaptive_window =
+        Some(AdaptiveRollbackWindow::new(5, 40).with_sample_frames(10));
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 1000 }).id();
+    tick(&mut app);
+    for _ in 0..20 {
+        snapshot_with_age(&mut app, e1, 1);
+        tick(&mut app);
+    }
+    assert_eq!(window(&app), 5);
+
+    // no snapshots, so the newest one keeps getting older: sampled at 3 frames old,
+    // then 8 more frames + margin of 2
+    for _ in 0..8 {
+        tick(&mut app);
+    }
+    assert_eq!(window(&app), 13);
+}
+
+#[test]
+fn despawn_marker_uses_current_window() {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    tick(&mut app);
+    app.world.entity_mut(e1).insert(DespawnMarker::new());
+    for _ in 0..5 {
+        tick(&mut app);
+    }
+    assert!(app.world.get_entity(e1).is_some());
+    // marker is older than the shrunk window, so despawn happens on the next tick
+    app.world.resource_mut::<TimewarpConfig>().rollback_window = 4;
+    tick(&mut app);
+    assert!(app.world.get_entity(e1).is_none());
+}
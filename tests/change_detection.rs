@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// never written by game logic
+#[derive(Component, Default, Debug, Clone, PartialEq)]
+struct Shield;
+
+/// entities each change detection filter matched during the last update
+#[derive(Resource, Default)]
+struct Seen {
+    shield_changed: Vec<Entity>,
+    enemy_changed_after_rollback: Vec<Entity>,
+}
+
+fn watch_changes(
+    mut seen: ResMut<Seen>,
+    q_shield: Query<Entity, Changed<Shield>>,
+    q_enemy: Query<Entity, Changed<ChangedAfterRollback<Enemy>>>,
+) {
+    seen.shield_changed = q_shield.iter().collect();
+    seen.enemy_changed_after_rollback = q_enemy.iter().collect();
+}
+
+#[test]
+fn rollback_only_reports_changed_values() {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.register_rollback::<Shield>();
+    app.init_resource::<Seen>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    app.add_systems(Update, watch_changes);
+
+    let e1 = app.world.spawn((Enemy { health: 10 }, Shield)).id();
+    let e2 = app.world.spawn((Enemy { health: 10 }, Shield)).id();
+
+    tick(&mut app); // frame 1
+    assert_eq!(app.world.resource::<Seen>().shield_changed.len(), 2);
+    for _ in 0..4 {
+        tick(&mut app); // frames 2..=5
+    }
+    assert!(app.world.resource::<Seen>().shield_changed.is_empty());
+
+    // a snapshot matching our prediction for e2 doesn't change anything
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e2)
+        .unwrap()
+        .insert(2, Enemy { health: 8 })
+        .unwrap();
+    // a snapshot for e1 that differs causes a rollback
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(2, Enemy { health: 100 })
+        .unwrap();
+
+    tick(&mut app); // frame 6, rollback to 3
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+
+    let seen = app.world.resource::<Seen>();
+    // restoring an unchanged Shield didn't trigger change detection
+    assert!(seen.shield_changed.is_empty());
+    // and only e1's Enemy ended up different to before the rollback
+    assert_eq!(seen.enemy_changed_after_rollback, vec![e1]);
+    assert_eq!(
+        app.world
+            .get::<ChangedAfterRollback<Enemy>>(e1)
+            .unwrap()
+            .frame,
+        5
+    );
+    assert!(app.world.get::<ChangedAfterRollback<Enemy>>(e2).is_none());
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 96);
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 4);
+
+    tick(&mut app); // frame 7
+    assert!(app
+        .world
+        .resource::<Seen>()
+        .enemy_changed_after_rollback
+        .is_empty());
+}
@@ -4,11 +4,6 @@ use bevy_timewarp::prelude::*;
 mod test_utils;
 use test_utils::*;
 
-fn inc_frame(mut game_clock: ResMut<GameClock>, rb: Option<Res<Rollback>>) {
-    game_clock.advance(1);
-    info!("FRAME --> {:?} rollback:{rb:?}", game_clock.frame());
-}
-
 fn take_damage(mut q: Query<(Entity, &mut Enemy, &EntName, Option<&Shield>)>) {
     for (entity, mut enemy, name, opt_shield) in q.iter_mut() {
         if opt_shield.is_none() {
@@ -51,7 +46,7 @@ fn component_add_and_remove() {
 
     app.add_systems(
         FixedUpdate,
-        (inc_frame, take_damage, log_all)
+        (take_damage, log_all)
             .chain()
             .in_set(TimewarpTestSets::GameLogic),
     );
@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+#[derive(Component, Debug, Clone, PartialEq)]
+struct Shield;
+
+/// shields break once health drops this low
+#[derive(Resource)]
+struct BreakThreshold(i32);
+
+fn break_shields(
+    q: Query<(Entity, &Enemy), With<Shield>>,
+    threshold: Res<BreakThreshold>,
+    mut commands: Commands,
+) {
+    for (entity, enemy) in q.iter() {
+        if enemy.health <= threshold.0 {
+            info!("{entity:?} shield broke {enemy:?}");
+            commands.entity(entity).remove::<Shield>();
+        }
+    }
+}
+
+fn setup_lifecycle_app(threshold: i32) -> (App, Entity) {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.register_rollback::<Shield>();
+    app.insert_resource(BreakThreshold(threshold));
+    app.add_systems(
+        FixedUpdate,
+        (take_damage, break_shields)
+            .chain()
+            .in_set(TimewarpTestSets::GameLogic),
+    );
+    let e1 = app.world.spawn((Enemy { health: 100 }, Shield)).id();
+    (app, e1)
+}
+
+/// like the shared rollback_to_frame_3, for tests that have already ticked to frame 5
+fn rollback_frame_6_to_3(app: &mut App, e1: Entity) {
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(2, Enemy { health: 99 })
+        .unwrap();
+    tick(app); // frame 6, rollback to 3
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+}
+
+#[test]
+fn removal_by_game_logic_is_recorded_during_rollback() {
+    let (mut app, e1) = setup_lifecycle_app(96);
+    for _ in 0..5 {
+        tick(&mut app);
+    }
+    // 96 health on frame 4
+    let ch = app.world.get::<ComponentHistory<Shield>>(e1).unwrap();
+    assert_eq!(ch.alive_ranges.ranges(), vec![(1, Some(4))]);
+
+    rollback_frame_6_to_3(&mut app, e1);
+
+    // one less damage taken, so the shield breaks a frame later
+    let ch = app.world.get::<ComponentHistory<Shield>>(e1).unwrap();
+    assert_eq!(ch.alive_ranges.ranges(), vec![(1, Some(5))]);
+    assert_eq!(app.comp_val_at::<Shield>(e1, 4), Some(&Shield));
+    assert_eq!(app.comp_val_at::<Shield>(e1, 5), None);
+    assert!(app.world.get::<Shield>(e1).is_none());
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 95);
+}
+
+#[test]
+fn removal_between_frames_is_replayed_during_rollback() {
+    let (mut app, e1) = setup_lifecycle_app(0);
+    for _ in 0..3 {
+        tick(&mut app);
+    }
+    app.world.entity_mut(e1).remove::<Shield>();
+    tick(&mut app); // frame 4
+    tick(&mut app); // frame 5
+
+    rollback_frame_6_to_3(&mut app, e1);
+
+    // game logic didn't remove it, so the removal before frame 4 is repeated
+    let ch = app.world.get::<ComponentHistory<Shield>>(e1).unwrap();
+    assert_eq!(ch.alive_ranges.ranges(), vec![(1, Some(4))]);
+    assert_eq!(app.comp_val_at::<Shield>(e1, 3), Some(&Shield));
+    assert!(app.world.get::<Shield>(e1).is_none());
+}
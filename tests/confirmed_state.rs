@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// e2 gets a correction for frame 2, and e1 for frame 4, in the same tick.
+/// with the default Newest consolidation strategy, we only rollback to frame 5.
+fn run_scenario(repredict_from_confirmed: bool) -> (App, Entity, Entity) {
+    let mut app = setup_test_app();
+    app.world
+        .resource_mut::<TimewarpConfig>()
+        .repredict_from_confirmed = repredict_from_confirmed;
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    let e2 = app.world.spawn(Enemy { health: 20 }).id();
+    for _ in 0..5 {
+        tick(&mut app);
+    }
+
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e2)
+        .unwrap()
+        .insert(2, Enemy { health: 200 })
+        .unwrap();
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(4, Enemy { health: 100 })
+        .unwrap();
+
+    let ss = app.world.get::<ServerSnapshot<Enemy>>(e2).unwrap();
+    assert_eq!(
+        ss.confirmed_at_or_before(4),
+        Some((2, &Enemy { health: 200 }))
+    );
+    assert_eq!(ss.confirmed_at_or_before(1), None);
+
+    tick(&mut app); // frame 6, rollback
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    (app, e1, e2)
+}
+
+#[test]
+fn stale_predictions_trusted_by_default() {
+    let (app, e1, e2) = run_scenario(false);
+
+    assert_eq!(app.world.resource::<PreviousRollback>().0.range.start, 5);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 98);
+    // e2's correction was older than the rollback frame, so we kept our prediction
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 14);
+}
+
+#[test]
+fn repredict_from_last_confirmed_frame() {
+    let (app, e1, e2) = run_scenario(true);
+
+    // e2 had no snapshot at frame 4, so we went back to its last confirmed value at 2
+    assert_eq!(app.world.resource::<PreviousRollback>().0.range.start, 3);
+    assert_eq!(app.comp_val_at::<Enemy>(e2, 3).unwrap().health, 199);
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 196);
+    // e1's snapshot was applied when resimulation reached frame 4
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 4).unwrap().health, 100);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 98);
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "debug_ui")]
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+#[test]
+fn debug_info_shows_entities_and_rollbacks() {
+    let mut app = setup_test_app();
+    app.register_rollback_with_correction_logging::<Enemy>();
+    app.add_plugins(TimewarpDebugPlugin::default());
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 100 }).id();
+    rollback_to_frame_3(&mut app, e1, Enemy { health: 50 });
+    tick(&mut app); // frame 7
+
+    let info = app.world.resource::<TimewarpDebugInfo>();
+    assert_eq!(info.frame, 7);
+    assert_eq!(info.stats.num_rollbacks, 1);
+
+    assert_eq!(info.rollbacks.len(), 1);
+    let rb = &info.rollbacks[0];
+    assert_eq!(rb.range, 3..5);
+    assert_eq!(rb.depth, 3);
+    assert_eq!(rb.triggered_by, vec![e1]);
+
+    assert_eq!(info.corrections.len(), 1);
+    assert_eq!(info.corrections[0].entity, e1);
+    assert_eq!(info.corrections[0].frame, 5);
+
+    let e1_info = info.entity(e1).unwrap();
+    assert_eq!(e1_info.rollback_triggers, 1);
+    assert_eq!(e1_info.last_snapshot_frame, 2);
+    let enemy = &e1_info.components[0];
+    assert!(enemy.type_name.ends_with("Enemy"));
+    assert_eq!(enemy.newest_frame, 7);
+    assert_eq!(enemy.occupancy, vec![true; 7]);
+    assert_eq!(enemy.alive_ranges, vec![(1, None)]);
+    assert_eq!(enemy.snapshot_frames, vec![2]);
+
+    let panel = info.to_string();
+    assert!(panel.contains("3..=5 depth 3"));
+    assert!(panel.contains("Enemy [#######] @ 7"));
+}
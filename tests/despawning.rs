@@ -4,11 +4,6 @@ use bevy_timewarp::prelude::*;
 mod test_utils;
 use test_utils::*;
 
-fn inc_frame(mut game_clock: ResMut<GameClock>, rb: Option<Res<Rollback>>) {
-    game_clock.advance(1);
-    info!("FRAME --> {:?} rollback:{rb:?}", game_clock.frame());
-}
-
 fn take_damage(mut q: Query<(Entity, &mut Enemy, &EntName)>) {
     for (entity, mut enemy, name) in q.iter_mut() {
         enemy.health -= 1;
@@ -30,7 +25,7 @@ fn despawn_markers() {
 
     app.add_systems(
         FixedUpdate,
-        (inc_frame, take_damage, log_all)
+        (take_damage, log_all)
             .chain()
             .in_set(TimewarpTestSets::GameLogic),
     );
@@ -102,7 +97,7 @@ fn despawn_revival_during_rollback() {
 
     app.add_systems(
         FixedUpdate,
-        (inc_frame, take_damage, log_all)
+        (take_damage, log_all)
             .chain()
             .in_set(TimewarpTestSets::GameLogic),
     );
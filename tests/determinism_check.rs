@@ -0,0 +1,82 @@
+use bevy::{ecs::event::ManualEventReader, prelude::*};
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// depends on how many times it has run, which isn't rolled back
+fn take_nondeterministic_damage(mut q: Query<&mut Enemy>, mut runs: Local<i32>) {
+    *runs += 1;
+    for mut enemy in q.iter_mut() {
+        enemy.health -= *runs;
+    }
+}
+
+fn setup_check_app(check: DeterminismCheck, nondeterministic: bool) -> (App, Entity) {
+    let mut app = setup_test_app();
+    app.world.resource_mut::<TimewarpConfig>().determinism_check = Some(check);
+    app.register_rollback::<Enemy>();
+    if nondeterministic {
+        app.add_systems(
+            FixedUpdate,
+            take_nondeterministic_damage.in_set(TimewarpTestSets::GameLogic),
+        );
+    } else {
+        app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    }
+    let e1 = app.world.spawn(Enemy { health: 1000 }).id();
+    (app, e1)
+}
+
+/// ticks, returning any determinism check failures
+fn tick_and_read(
+    app: &mut App,
+    reader: &mut ManualEventReader<DeterminismCheckFailed>,
+) -> Vec<DeterminismCheckFailed> {
+    tick(app);
+    let events = app.world.resource::<Events<DeterminismCheckFailed>>();
+    reader.iter(events).cloned().collect()
+}
+
+#[test]
+fn deterministic_simulation_passes() {
+    let (mut app, e1) = setup_check_app(DeterminismCheck::new(5, 3), false);
+    let mut reader = ManualEventReader::default();
+    for _ in 0..12 {
+        assert!(tick_and_read(&mut app, &mut reader).is_empty());
+    }
+    // checks requested on frames 5 and 10
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 2);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 988);
+    assert!(app.world.get::<TimewarpCorrection<Enemy>>(e1).is_none());
+}
+
+#[test]
+fn nondeterministic_simulation_reports_first_divergence() {
+    let (mut app, e1) = setup_check_app(DeterminismCheck::new(5, 3), true);
+    let mut reader = ManualEventReader::default();
+    let mut failures = Vec::new();
+    for _ in 0..7 {
+        failures.extend(tick_and_read(&mut app, &mut reader));
+    }
+    // requested while simulating frame 6, resimulating 3..=5
+    assert_eq!(
+        failures,
+        vec![DeterminismCheckFailed {
+            range: 3..5,
+            frame: 3,
+            entity: e1,
+            component_type: std::any::type_name::<Enemy>(),
+        }]
+    );
+}
+
+#[test]
+#[should_panic(expected = "Determinism check failed")]
+fn nondeterministic_simulation_can_panic() {
+    let check = DeterminismCheck::new(5, 3).with_panic_on_failure(true);
+    let (mut app, _e1) = setup_check_app(check, true);
+    for _ in 0..7 {
+        tick(&mut app);
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+#[derive(Component, Debug, Clone, PartialEq)]
+struct Target(Entity);
+
+impl MapTimewarpEntities for Target {
+    fn map_timewarp_entities(&mut self, map: &TimewarpEntityMap) {
+        self.0 = map.get(self.0);
+    }
+}
+
+#[test]
+fn remap_entities_in_history_and_snapshots() {
+    let mut app = setup_test_app();
+    app.register_rollback::<Target>();
+    app.register_entity_mapping::<Target>();
+
+    let predicted = app.world.spawn_empty().id();
+    let confirmed = app.world.spawn_empty().id();
+    let other = app.world.spawn_empty().id();
+    let e1 = app.world.spawn(Target(predicted)).id();
+    let e2 = app.world.spawn(Target(other)).id();
+
+    for _ in 0..4 {
+        tick(&mut app);
+    }
+    app.world
+        .get_mut::<ServerSnapshot<Target>>(e1)
+        .unwrap()
+        .insert(4, Target(predicted))
+        .unwrap();
+    tick(&mut app); // frame 5
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 0);
+
+    app.world.send_event(RemapTimewarpEntities(
+        TimewarpEntityMap::default().with(predicted, confirmed),
+    ));
+    tick(&mut app); // frame 6
+
+    assert_eq!(app.world.get::<Target>(e1).unwrap().0, confirmed);
+    for frame in 1..=6 {
+        assert_eq!(app.comp_val_at::<Target>(e1, frame).unwrap().0, confirmed);
+    }
+    let ss = app.world.get::<ServerSnapshot<Target>>(e1).unwrap();
+    assert_eq!(ss.at_frame(4).unwrap().0, confirmed);
+    // remapping isn't a new snapshot, so no rollback
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 0);
+
+    // unaffected references are left alone
+    assert_eq!(app.world.get::<Target>(e2).unwrap().0, other);
+    assert_eq!(app.comp_val_at::<Target>(e2, 3).unwrap().0, other);
+}
+
+#[test]
+fn remap_entities_in_server_mode() {
+    let tw_config = TimewarpConfig::new(TimewarpTestSets::GameLogic, TimewarpTestSets::GameLogic)
+        .with_mode(TimewarpMode::Server)
+        .with_rollback_window(TEST_ROLLBACK_WINDOW)
+        .with_schedule(FixedUpdate);
+    let mut app = App::new();
+    app.add_plugins(TimewarpPlugin::new(tw_config));
+    app.add_plugins(bevy::time::TimePlugin);
+    app.insert_resource(FixedTime::new(TIMESTEP));
+    app.register_rollback::<Target>();
+    app.register_entity_mapping::<Target>();
+
+    let predicted = app.world.spawn_empty().id();
+    let confirmed = app.world.spawn_empty().id();
+    let e1 = app.world.spawn(Target(predicted)).id();
+    for _ in 0..4 {
+        tick(&mut app);
+    }
+    assert!(app.world.get::<ServerSnapshot<Target>>(e1).is_none());
+
+    app.world.send_event(RemapTimewarpEntities(
+        TimewarpEntityMap::default().with(predicted, confirmed),
+    ));
+    tick(&mut app); // frame 5
+
+    assert_eq!(app.world.get::<Target>(e1).unwrap().0, confirmed);
+    for frame in 1..=5 {
+        assert_eq!(app.comp_val_at::<Target>(e1, frame).unwrap().0, confirmed);
+    }
+}
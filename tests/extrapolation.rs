@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+#[derive(Component, Default, Debug, Clone, PartialEq)]
+struct Position(i32);
+
+#[derive(Component, Default, Debug, Clone, PartialEq)]
+struct Velocity(i32);
+
+impl TimewarpExtrapolate for Position {
+    type Rate = Velocity;
+    fn extrapolate(&self, velocity: &Velocity, frames: FrameNumber) -> Self {
+        Position(self.0 + velocity.0 * frames as i32)
+    }
+}
+
+/// moved by our game logic. remote entities are only updated by snapshots.
+#[derive(Component)]
+struct Local;
+
+fn movement(mut q: Query<(&mut Position, &Velocity), With<Local>>) {
+    for (mut pos, vel) in q.iter_mut() {
+        pos.0 += vel.0;
+    }
+}
+
+fn setup_app(max_extrapolation_age: FrameNumber) -> (App, Entity, Entity) {
+    let tw_config = TimewarpConfig::new(TimewarpTestSets::GameLogic, TimewarpTestSets::GameLogic)
+        .with_rollback_window(TEST_ROLLBACK_WINDOW)
+        .with_max_extrapolation_age(max_extrapolation_age)
+        .with_schedule(FixedUpdate);
+    let mut app = App::new();
+    app.add_plugins(TimewarpPlugin::new(tw_config));
+    app.add_plugins(bevy::time::TimePlugin);
+    app.insert_resource(FixedTime::new(TIMESTEP));
+    app.register_rollback::<Position>();
+    app.register_rollback::<Velocity>();
+    app.register_extrapolation::<Position>();
+    app.add_systems(FixedUpdate, movement.in_set(TimewarpTestSets::GameLogic));
+
+    let local = app.world.spawn((Position(0), Velocity(1), Local)).id();
+    let remote = app.world.spawn((Position(0), Velocity(1))).id();
+
+    tick(&mut app); // frame 1
+    tick(&mut app); // frame 2
+
+    // both arrive in time for frame 2, no rollback
+    SnapshotBatch::partial(2)
+        .with_component(local, Position(2))
+        .with_component(local, Velocity(1))
+        .with_component(remote, Position(5))
+        .with_component(remote, Velocity(1))
+        .apply_to_world(&mut app.world)
+        .unwrap();
+    for _ in 0..3 {
+        tick(&mut app); // frames 3..=5
+    }
+    assert_eq!(app.world.get::<Position>(remote).unwrap().0, 5);
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 0);
+
+    // remote entity missing, the local one was predicted correctly
+    SnapshotBatch::partial(4)
+        .with_component(local, Position(4))
+        .with_component(local, Velocity(1))
+        .apply_to_world(&mut app.world)
+        .unwrap();
+    tick(&mut app); // frame 6
+    (app, local, remote)
+}
+
+#[test]
+fn missing_entity_is_extrapolated() {
+    let (app, local, remote) = setup_app(10);
+
+    // remote was estimated at 5 + 2 frames * 1
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert_eq!(app.comp_val_at::<Position>(remote, 4).unwrap().0, 7);
+    assert_eq!(app.world.get::<Position>(remote).unwrap().0, 7);
+    assert_eq!(app.world.get::<Position>(local).unwrap().0, 6);
+}
+
+#[test]
+fn extrapolation_respects_max_age() {
+    let (app, local, remote) = setup_app(1);
+
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 0);
+    assert_eq!(app.world.get::<Position>(remote).unwrap().0, 5);
+    assert_eq!(app.world.get::<Position>(local).unwrap().0, 6);
+}
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// a tick counter shared with some other networking layer
+#[derive(Resource, Default, Debug)]
+struct NetTick(u32);
+
+impl FrameSource for NetTick {
+    fn frame(&self) -> FrameNumber {
+        self.0
+    }
+    fn set_frame(&mut self, frame: FrameNumber) {
+        self.0 = frame;
+    }
+}
+
+/// frames simulated, in order, as seen by game logic
+#[derive(Resource, Default)]
+struct Simulated(Vec<u32>);
+
+fn record_tick(tick: Res<NetTick>, mut simulated: ResMut<Simulated>) {
+    simulated.0.push(tick.0);
+}
+
+#[test]
+fn rollback_rewinds_custom_frame_source() {
+    let mut app = setup_test_app_for::<NetTick>(test_config());
+    app.init_resource::<Simulated>();
+    TimewarpApp::<NetTick>::new(&mut app).register_rollback::<Enemy>();
+    app.add_systems(
+        FixedUpdate,
+        (record_tick, take_damage)
+            .chain()
+            .in_set(TimewarpTestSets::GameLogic),
+    );
+
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    for _ in 0..4 {
+        tick(&mut app); // frames 1..=4
+    }
+    assert_eq!(app.world.resource::<NetTick>().0, 4);
+    // timewarp uses our tick directly
+    assert!(app.world.get_resource::<GameClock>().is_none());
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 3).unwrap().health, 7);
+
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(2, Enemy { health: 100 })
+        .unwrap();
+    tick(&mut app); // frame 5, rollback to 3
+
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert_eq!(
+        app.world.resource::<Simulated>().0,
+        vec![1, 2, 3, 4, 3, 4, 5]
+    );
+    assert_eq!(app.world.resource::<NetTick>().0, 5);
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 3).unwrap().health, 99);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 97);
+}
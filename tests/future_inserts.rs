@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// the frame some remote input says an enemy will spawn at, and the entity to spawn it on.
+#[derive(Resource)]
+struct ScheduledSpawn(FrameNumber, Entity);
+
+/// game logic that learns on frame 3 about a spawn 2 frames ahead.
+fn schedule_spawn(
+    game_clock: Res<GameClock>,
+    scheduled: Res<ScheduledSpawn>,
+    mut commands: Commands,
+) {
+    if game_clock.frame() == scheduled.0 - 2 {
+        commands
+            .entity(scheduled.1)
+            .insert(InsertComponentAtFrame::new(
+                scheduled.0,
+                Enemy { health: 10 },
+            ));
+    }
+}
+
+#[test]
+fn future_icaf_applied_when_frame_arrives() {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e2 = app.world.spawn(Enemy { health: 10 }).id();
+
+    tick(&mut app); // frame 1
+    tick(&mut app); // frame 2
+    let e1 = app
+        .world
+        .spawn(InsertComponentAtFrame::new(4, Enemy { health: 10 }))
+        .id();
+    tick(&mut app); // frame 3
+    tick(&mut app); // frame 4
+    assert!(app.world.get::<Enemy>(e1).is_none());
+    assert!(app.world.get::<InsertComponentAtFrame<Enemy>>(e1).is_some());
+
+    tick(&mut app); // frame 5, inserted before simulating it
+    assert!(app.world.get::<InsertComponentAtFrame<Enemy>>(e1).is_none());
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 4).unwrap().health, 10);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 9);
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 0);
+
+    // a rollback to before it was inserted doesn't lose it
+    tick(&mut app); // frame 6
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e2)
+        .unwrap()
+        .insert(3, Enemy { health: 100 })
+        .unwrap();
+    tick(&mut app); // frame 7, rollback to 4
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 4).unwrap().health, 10);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 7);
+}
+
+#[test]
+fn future_icaf_applied_during_resimulation() {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    let spawned = app.world.spawn_empty().id();
+    app.insert_resource(ScheduledSpawn(5, spawned));
+    app.add_systems(
+        FixedUpdate,
+        (schedule_spawn, take_damage).in_set(TimewarpTestSets::GameLogic),
+    );
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+
+    for _ in 0..6 {
+        tick(&mut app); // frames 1..=6
+    }
+    assert_eq!(app.comp_val_at::<Enemy>(spawned, 5).unwrap().health, 10);
+    assert_eq!(app.world.get::<Enemy>(spawned).unwrap().health, 9);
+
+    // rollback to before it was scheduled, and the rescheduled insert is applied again
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(2, Enemy { health: 100 })
+        .unwrap();
+    tick(&mut app); // frame 7, rollback to 3
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert!(app
+        .world
+        .get::<InsertComponentAtFrame<Enemy>>(spawned)
+        .is_none());
+    assert_eq!(app.comp_val_at::<Enemy>(spawned, 5).unwrap().health, 10);
+    assert_eq!(app.comp_val_at::<Enemy>(spawned, 6).unwrap().health, 9);
+    assert_eq!(app.world.get::<Enemy>(spawned).unwrap().health, 8);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 95);
+}
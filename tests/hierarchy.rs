@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+#[derive(Component)]
+struct Ship;
+
+#[derive(Component)]
+struct Turret;
+
+/// set in tests to attach or detach turrets at a given frame
+#[derive(Resource, Default)]
+struct HierarchyChange {
+    attach_at: Option<FrameNumber>,
+    detach_at: Option<FrameNumber>,
+}
+
+fn change_hierarchy(
+    q_ship: Query<Entity, With<Ship>>,
+    q_turret: Query<Entity, With<Turret>>,
+    change: Res<HierarchyChange>,
+    game_clock: Res<GameClock>,
+    mut commands: Commands,
+) {
+    let ship = q_ship.single();
+    for turret in q_turret.iter() {
+        if change.attach_at == Some(game_clock.frame()) {
+            commands.entity(ship).add_child(turret);
+        }
+        if change.detach_at == Some(game_clock.frame()) {
+            commands.entity(turret).remove_parent();
+        }
+    }
+}
+
+/// only turrets attached to a ship take damage
+fn take_damage(mut q: Query<(Entity, &mut Enemy), With<Parent>>) {
+    for (entity, mut enemy) in q.iter_mut() {
+        enemy.health -= 1;
+        info!("{entity:?} took 1 damage -> {enemy:?}");
+    }
+}
+
+fn setup_hierarchy_app(change: HierarchyChange, attached: bool) -> (App, Entity, Entity) {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.register_rollback_hierarchy();
+    app.insert_resource(change);
+    app.add_systems(
+        FixedUpdate,
+        (change_hierarchy, apply_deferred, take_damage)
+            .chain()
+            .in_set(TimewarpTestSets::GameLogic),
+    );
+    let ship = app.world.spawn((Ship, Enemy { health: 100 })).id();
+    let turret = app.world.spawn((Turret, Enemy { health: 20 })).id();
+    if attached {
+        app.world.entity_mut(ship).add_child(turret);
+    }
+    (app, ship, turret)
+}
+
+#[test]
+fn rollback_detaches_child_attached_during_window() {
+    let change = HierarchyChange {
+        attach_at: Some(4),
+        detach_at: None,
+    };
+    let (mut app, ship, turret) = setup_hierarchy_app(change, false);
+    rollback_to_frame_3(&mut app, ship, Enemy { health: 99 });
+
+    // the turret wasn't attached at frame 2, so it's detached until resimulating frame 4
+    // otherwise it would have taken damage during frame 3 as well.
+    assert_eq!(app.world.get::<Parent>(turret).unwrap().get(), ship);
+    assert_eq!(app.world.get::<Enemy>(turret).unwrap().health, 17);
+    assert_eq!(
+        app.comp_val_at::<TimewarpParent>(turret, 4),
+        Some(&TimewarpParent(ship))
+    );
+    assert_eq!(app.comp_val_at::<TimewarpParent>(turret, 3), None);
+}
+
+#[test]
+fn rollback_reattaches_child_detached_during_window() {
+    let change = HierarchyChange {
+        attach_at: None,
+        detach_at: Some(4),
+    };
+    let (mut app, ship, turret) = setup_hierarchy_app(change, true);
+    rollback_to_frame_3(&mut app, ship, Enemy { health: 99 });
+
+    // attached again for frame 3, so took damage on frames 1..=3
+    assert!(app.world.get::<Parent>(turret).is_none());
+    assert!(!app
+        .world
+        .get::<Children>(ship)
+        .is_some_and(|c| c.contains(&turret)));
+    assert_eq!(app.world.get::<Enemy>(turret).unwrap().health, 17);
+}
+
+#[test]
+fn despawn_marker_propagates_to_children() {
+    let (mut app, ship, turret) = setup_hierarchy_app(HierarchyChange::default(), true);
+    tick(&mut app);
+    tick(&mut app);
+
+    app.world.entity_mut(ship).insert(DespawnMarker::new());
+    tick(&mut app);
+    assert!(app.world.get::<DespawnMarker>(turret).is_some());
+    assert!(app.world.get::<Enemy>(turret).is_none());
+    assert!(app.world.get::<Enemy>(ship).is_none());
+
+    for _ in 0..TEST_ROLLBACK_WINDOW {
+        tick(&mut app);
+    }
+    assert!(app.world.get_entity(ship).is_none());
+    assert!(app.world.get_entity(turret).is_none());
+}
+
+#[test]
+fn despawn_marker_propagates_to_children_attached_later() {
+    let change = HierarchyChange {
+        attach_at: Some(5),
+        ..default()
+    };
+    let (mut app, ship, turret) = setup_hierarchy_app(change, false);
+    tick(&mut app);
+    tick(&mut app);
+
+    app.world.entity_mut(ship).insert(DespawnMarker::new());
+    tick(&mut app); // frame 3
+    assert!(app.world.get::<DespawnMarker>(turret).is_none());
+
+    tick(&mut app); // frame 4
+    tick(&mut app); // frame 5, turret attached to the marked ship
+    assert_eq!(app.world.get::<Parent>(turret).unwrap().get(), ship);
+    let ship_marker = app.world.get::<DespawnMarker>(ship).unwrap().0;
+    assert_eq!(
+        app.world.get::<DespawnMarker>(turret).unwrap().0,
+        ship_marker
+    );
+    assert!(app.world.get::<Enemy>(turret).is_none());
+
+    // despawned along with the ship, at the ship's despawn frame
+    for _ in 0..TEST_ROLLBACK_WINDOW - 2 {
+        tick(&mut app);
+    }
+    assert!(app.world.get_entity(ship).is_none());
+    assert!(app.world.get_entity(turret).is_none());
+}
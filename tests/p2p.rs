@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// how far each player moves this frame
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Move(i32);
+
+#[derive(Component)]
+struct Player(PlayerHandle);
+
+/// players start moving on frame 3 (the first frame can't be rolled back to).
+/// player 0 always moves 1, player 1 changes direction every 4 frames
+#[allow(clippy::manual_is_multiple_of)] // is_multiple_of needs rust 1.87
+fn local_input(mut session: ResMut<P2pSession<Move>>, game_clock: Res<GameClock>) {
+    let frame = game_clock.frame();
+    let input = match session.local_player() {
+        _ if frame < 3 => Move(0),
+        0 => Move(1),
+        _ => Move(if (frame / 4) % 2 == 0 { 1 } else { -1 }),
+    };
+    session.add_local_input(frame, input);
+}
+
+fn apply_inputs(
+    mut session: ResMut<P2pSession<Move>>,
+    mut q: Query<(&Player, &mut Enemy)>,
+    game_clock: Res<GameClock>,
+) {
+    for (player, mut pos) in q.iter_mut() {
+        pos.health += session.input(player.0, game_clock.frame()).0;
+    }
+}
+
+fn setup_peer(local_player: PlayerHandle) -> (App, [Entity; 2]) {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.add_plugins(TimewarpP2pPlugin::<Move>::new(2, local_player));
+    app.add_systems(
+        FixedUpdate,
+        (local_input.run_if(not_in_rollback()), apply_inputs)
+            .chain()
+            .in_set(TimewarpTestSets::GameLogic),
+    );
+    let players = [
+        app.world.spawn((Player(0), Enemy { health: 0 })).id(),
+        app.world.spawn((Player(1), Enemy { health: 0 })).id(),
+    ];
+    (app, players)
+}
+
+fn pos_at(app: &App, e: Entity, frame: FrameNumber) -> i32 {
+    app.comp_val_at::<Enemy>(e, frame).unwrap().health
+}
+
+/// each peer's inputs arrive at the other `latency` ticks later
+#[test]
+fn peers_agree_after_late_inputs() {
+    let latency = 3;
+    let mut peers = [setup_peer(0), setup_peer(1)];
+    let mut in_flight: Vec<(usize, Vec<P2pInputMessage<Move>>)> = Vec::new();
+
+    for tick_num in 0..20 {
+        for (app, _) in peers.iter_mut() {
+            tick(app);
+        }
+        for (i, (app, _)) in peers.iter_mut().enumerate() {
+            let msgs = app
+                .world
+                .resource_mut::<P2pSession<Move>>()
+                .drain_outgoing();
+            in_flight.push((tick_num + latency, msgs));
+            // deliver anything due to this peer, sent by the other one
+            let due = in_flight
+                .iter()
+                .filter(|(due_at, msgs)| {
+                    *due_at == tick_num && msgs.first().is_some_and(|m| m.player != i)
+                })
+                .flat_map(|(_, msgs)| msgs.iter().cloned())
+                .collect::<Vec<_>>();
+            let mut session = app.world.resource_mut::<P2pSession<Move>>();
+            for msg in due {
+                session.receive(msg).unwrap();
+            }
+        }
+    }
+    tick(&mut peers[0].0);
+    tick(&mut peers[1].0);
+
+    // player 1 changed direction after we'd predicted it wouldn't
+    let (app0, players0) = &peers[0];
+    let (app1, players1) = &peers[1];
+    assert!(app0.world.resource::<RollbackStats>().num_rollbacks > 0);
+
+    let confirmed = app0.world.resource::<P2pSession<Move>>().confirmed_frame();
+    assert_eq!(confirmed, 17);
+    assert_eq!(**app0.world.resource::<P2pConfirmedFrame>(), confirmed);
+
+    // both peers simulated the same positions for the confirmed frame
+    for p in 0..2 {
+        assert_eq!(
+            pos_at(app0, players0[p], confirmed),
+            pos_at(app1, players1[p], confirmed)
+        );
+    }
+    // player 1 moved +1 for frame 3, -1 for 4-7, +1 for 8-11, -1 for 12-15, +1 for 16-17
+    assert_eq!(pos_at(app0, players0[0], 17), 15);
+    assert_eq!(pos_at(app0, players0[1], 17), -1);
+
+    // nothing older than the confirmed frame is kept
+    let ch = app0
+        .world
+        .get::<ComponentHistory<Enemy>>(players0[0])
+        .unwrap();
+    assert_eq!(ch.values.oldest_frame(), confirmed);
+    // but it's still known to be alive
+    assert!(ch.alive_at_frame(confirmed));
+}
+
+#[test]
+fn input_buffers_follow_rollback_window() {
+    let (mut app, _) = setup_peer(0);
+    tick(&mut app);
+    let len = |app: &App| app.world.resource::<P2pSession<Move>>().buffer_len();
+    assert_eq!(len(&app), TEST_ROLLBACK_WINDOW as usize * 2);
+
+    app.world.resource_mut::<TimewarpConfig>().rollback_window = 25;
+    tick(&mut app);
+    assert_eq!(len(&app), 50);
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// how many frames an entity was resimulated for
+#[derive(Component, Default, Debug)]
+struct Resimulated(u32);
+
+fn take_damage(mut q: Query<(Entity, &mut Enemy), Without<SkipResimulation>>) {
+    for (entity, mut enemy) in q.iter_mut() {
+        enemy.health -= 1;
+        info!("{entity:?} took 1 damage -> {enemy:?}");
+    }
+}
+
+fn count_resimulations(mut q: Query<&mut Resimulated, Without<SkipResimulation>>) {
+    for mut resimulated in q.iter_mut() {
+        resimulated.0 += 1;
+    }
+}
+
+fn run_scenario(partial_rollback: bool) -> (App, Entity, Entity, Entity) {
+    let mut app = setup_test_app();
+    app.world.resource_mut::<TimewarpConfig>().partial_rollback = partial_rollback;
+    app.register_rollback::<Enemy>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            take_damage,
+            count_resimulations.run_if(resource_exists::<Rollback>()),
+        )
+            .chain()
+            .in_set(TimewarpTestSets::GameLogic),
+    );
+    let e1 = app
+        .world
+        .spawn((
+            Enemy { health: 10 },
+            RollbackIsland(1),
+            Resimulated::default(),
+        ))
+        .id();
+    let e2 = app
+        .world
+        .spawn((
+            Enemy { health: 20 },
+            RollbackIsland(2),
+            Resimulated::default(),
+        ))
+        .id();
+    // no island, so always part of a rollback
+    let e3 = app
+        .world
+        .spawn((Enemy { health: 30 }, Resimulated::default()))
+        .id();
+    rollback_to_frame_3(&mut app, e1, Enemy { health: 100 });
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 96);
+    // resimulated frames 3, 4 and 5, then frame 6 ran normally
+    assert_eq!(app.world.get::<Resimulated>(e1).unwrap().0, 3);
+    assert_eq!(app.world.get::<Resimulated>(e3).unwrap().0, 3);
+    assert_eq!(app.world.get::<Enemy>(e3).unwrap().health, 24);
+    (app, e1, e2, e3)
+}
+
+#[test]
+fn partial_rollback_skips_other_islands() {
+    let (mut app, _e1, e2, _e3) = run_scenario(true);
+
+    let rb = &app.world.resource::<PreviousRollback>().0;
+    assert!(rb.islands.as_ref().unwrap().contains(&1));
+    assert!(!rb.affects(Some(&RollbackIsland(2))));
+
+    // e2 wasn't restored or resimulated, and its history is untouched
+    assert_eq!(app.world.get::<Resimulated>(e2).unwrap().0, 0);
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 14);
+    assert_eq!(app.comp_val_at::<Enemy>(e2, 3).unwrap().health, 17);
+    assert!(app.world.get::<SkipResimulation>(e2).is_none());
+
+    tick(&mut app); // frame 7
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 13);
+}
+
+#[test]
+fn full_rollback_by_default() {
+    let (app, _e1, e2, _e3) = run_scenario(false);
+
+    assert!(app.world.resource::<PreviousRollback>().0.islands.is_none());
+    assert_eq!(app.world.get::<Resimulated>(e2).unwrap().0, 3);
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 14);
+}
@@ -0,0 +1,116 @@
+use bevy::{ecs::event::ManualEventReader, prelude::*};
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Effect {
+    Sparks,
+}
+
+/// how many times we actually spawned the effect
+#[derive(Resource, Default)]
+struct SparksSpawned(u32);
+
+/// takes damage every frame, with sparks whenever health is a multiple of 4
+fn take_damage(
+    mut q: Query<(Entity, &mut Enemy)>,
+    mut effects: PredictedEffects<Effect>,
+    mut spawned: ResMut<SparksSpawned>,
+) {
+    for (entity, mut enemy) in q.iter_mut() {
+        enemy.health -= 1;
+        if enemy.health % 4 == 0 && effects.emit(entity, Effect::Sparks) {
+            spawned.0 += 1;
+        }
+    }
+}
+
+fn setup_effects_app(force_rollback: bool) -> (App, Entity) {
+    let mut app = setup_test_app();
+    app.world
+        .resource_mut::<TimewarpConfig>()
+        .force_rollback_always = force_rollback;
+    app.register_rollback::<Enemy>();
+    app.add_predicted_effects::<Effect>();
+    app.init_resource::<SparksSpawned>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    (app, e1)
+}
+
+/// ticks, returning any effects cancelled
+fn tick_and_read_cancelled(
+    app: &mut App,
+    num_ticks: usize,
+    reader: &mut ManualEventReader<PredictedEffectUpdate<Effect>>,
+) -> Vec<(FrameNumber, PredictedEffect<Effect>)> {
+    let mut cancelled = Vec::new();
+    for _ in 0..num_ticks {
+        tick(app);
+        let events = app
+            .world
+            .resource::<Events<PredictedEffectUpdate<Effect>>>();
+        for update in reader.iter(events) {
+            if let TimewarpEventUpdate::Cancelled { frame, event } = update {
+                cancelled.push((*frame, event.clone()));
+            }
+        }
+    }
+    cancelled
+}
+
+fn sparks_spawned(app: &App) -> u32 {
+    app.world.resource::<SparksSpawned>().0
+}
+
+#[test]
+fn reemitted_effects_are_suppressed() {
+    let (mut app, e1) = setup_effects_app(true);
+    let mut reader = ManualEventReader::default();
+
+    assert!(tick_and_read_cancelled(&mut app, 5, &mut reader).is_empty());
+    assert_eq!(sparks_spawned(&app), 1);
+
+    // agrees with our prediction, but we rollback anyway and resimulate frame 2
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(1, Enemy { health: 9 })
+        .unwrap();
+    let cancelled = tick_and_read_cancelled(&mut app, 1, &mut reader); // frame 6
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert!(cancelled.is_empty());
+    // the sparks from frame 2 weren't spawned again, but frame 6 has new ones
+    assert_eq!(sparks_spawned(&app), 2);
+}
+
+#[test]
+fn effects_that_no_longer_happen_are_cancelled() {
+    let (mut app, e1) = setup_effects_app(false);
+    let mut reader = ManualEventReader::default();
+
+    tick_and_read_cancelled(&mut app, 5, &mut reader);
+    assert_eq!(sparks_spawned(&app), 1);
+
+    // we had one more health, so the sparks were on frame 3, not 2
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(1, Enemy { health: 10 })
+        .unwrap();
+    let cancelled = tick_and_read_cancelled(&mut app, 1, &mut reader); // frame 6
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert_eq!(
+        cancelled,
+        vec![(
+            2,
+            PredictedEffect {
+                entity: e1,
+                kind: Effect::Sparks
+            }
+        )]
+    );
+    assert_eq!(sparks_spawned(&app), 2);
+}
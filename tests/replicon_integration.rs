@@ -0,0 +1,85 @@
+#![cfg(feature = "replicon")]
+use bevy::prelude::*;
+use bevy_replicon::{
+    bincode::{DefaultOptions, Options},
+    prelude::*,
+    renet::Bytes,
+};
+use bevy_timewarp::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+mod test_utils;
+use test_utils::*;
+
+#[derive(Component, Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Health(i32);
+
+fn take_damage(mut q: Query<(Entity, &mut Health)>) {
+    for (entity, mut health) in q.iter_mut() {
+        health.0 -= 1;
+        info!("{entity:?} took 1 damage -> {health:?}");
+    }
+}
+
+fn replicon_tick_at(frame: FrameNumber) -> RepliconTick {
+    let mut tick = RepliconTick::default();
+    tick.increment_by(frame);
+    tick
+}
+
+#[test]
+fn replicon_deserializer_inserts_at_tick() {
+    let mut app = setup_test_app();
+    app.add_plugins(TimewarpRepliconPlugin::new());
+    app.register_rollback::<Health>();
+
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+
+    let e1 = app.world.spawn(Health(10)).id();
+
+    tick(&mut app); // frame 1
+    tick(&mut app); // frame 2
+    tick(&mut app); // frame 3
+    tick(&mut app); // frame 4
+
+    assert_eq!(app.world.get::<Health>(e1).unwrap().0, 6);
+
+    // pretend replicon received a Health value for frame 2 from the server
+    let bytes = DefaultOptions::new().serialize(&Health(100)).unwrap();
+    let mut cursor = Cursor::new(Bytes::from(bytes));
+    deserialize_component_timewarp::<Health>(
+        &mut app.world.entity_mut(e1),
+        &mut ServerEntityMap::default(),
+        &mut cursor,
+        replicon_tick_at(2),
+    )
+    .unwrap();
+
+    assert_eq!(
+        app.world
+            .get::<ServerSnapshot<Health>>(e1)
+            .unwrap()
+            .at_frame(2),
+        Some(&Health(100))
+    );
+
+    tick(&mut app); // frame 5, rollback to 3
+
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert_eq!(app.comp_val_at::<Health>(e1, 3).unwrap().0, 99);
+    assert_eq!(app.world.get::<Health>(e1).unwrap().0, 97);
+}
+
+#[test]
+fn replicon_tick_follows_game_clock() {
+    let mut app = setup_test_app();
+    app.add_plugins(TimewarpRepliconPlugin::new());
+    app.init_resource::<RepliconTick>();
+
+    tick(&mut app); // frame 1
+    tick(&mut app); // frame 2
+    tick(&mut app); // frame 3
+
+    assert_eq!(app.world.resource::<RepliconTick>().get(), 3);
+}
@@ -0,0 +1,74 @@
+use bevy::{
+    ecs::event::{Event, ManualEventReader},
+    prelude::*,
+};
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+fn read<E: Event + Clone>(app: &App, reader: &mut ManualEventReader<E>) -> Vec<E> {
+    reader
+        .iter(app.world.resource::<Events<E>>())
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn rollback_lifecycle_events() {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    let e2 = app.world.spawn(Enemy { health: 20 }).id();
+
+    let mut started_reader = ManualEventReader::<RollbackStarted>::default();
+    let mut resimulated_reader = ManualEventReader::<FrameResimulated>::default();
+    let mut completed_reader = ManualEventReader::<RollbackCompleted>::default();
+
+    for _ in 0..5 {
+        tick(&mut app);
+    }
+    assert!(read(&app, &mut started_reader).is_empty());
+
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(2, Enemy { health: 100 })
+        .unwrap();
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e2)
+        .unwrap()
+        .insert(2, Enemy { health: 200 })
+        .unwrap();
+    tick(&mut app); // frame 6, rollback to 3
+
+    let started = read(&app, &mut started_reader);
+    assert_eq!(started.len(), 1);
+    assert_eq!(started[0].range, 3..5);
+    assert_eq!(started[0].depth, 3);
+    let mut triggered_by = started[0].triggered_by.clone();
+    triggered_by.sort();
+    assert_eq!(triggered_by, vec![e1, e2]);
+    assert_eq!(
+        started[0].component_types,
+        vec![std::any::type_name::<Enemy>()]
+    );
+
+    let resimulated = read(&app, &mut resimulated_reader);
+    assert_eq!(
+        resimulated.iter().map(|ev| ev.frame).collect::<Vec<_>>(),
+        vec![3, 4, 5]
+    );
+
+    let completed = read(&app, &mut completed_reader);
+    assert_eq!(completed.len(), 1);
+    assert_eq!(completed[0].range, 3..5);
+    assert_eq!(completed[0].depth, 3);
+    assert_eq!(completed[0].triggered_by.len(), 2);
+
+    tick(&mut app); // frame 7
+    assert!(read(&app, &mut started_reader).is_empty());
+    assert!(read(&app, &mut resimulated_reader).is_empty());
+    assert!(read(&app, &mut completed_reader).is_empty());
+}
@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+use std::sync::{Arc, Mutex};
+
+mod test_utils;
+use test_utils::*;
+
+#[derive(Debug, Clone, PartialEq)]
+enum HookCall {
+    Restore {
+        from: i32,
+        to: i32,
+        frame: FrameNumber,
+    },
+    Rebirth {
+        health: i32,
+        frame: FrameNumber,
+    },
+    Rekill {
+        frame: FrameNumber,
+    },
+}
+
+/// stands in for engine-side state that needs fixing up
+#[derive(Component, Debug, PartialEq)]
+struct Synced(i32);
+
+fn setup_app_with_hooks() -> (App, Arc<Mutex<Vec<HookCall>>>) {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let (restores, rebirths, rekills) = (calls.clone(), calls.clone(), calls.clone());
+    let hooks = RollbackHooks::<Enemy>::default()
+        .on_restore(move |current, restored, frame, _entity| {
+            restores.lock().unwrap().push(HookCall::Restore {
+                from: current.health,
+                to: restored.health,
+                frame,
+            });
+            current.set_if_neq(restored.clone());
+        })
+        .on_rebirth(move |comp, frame, _entity| {
+            rebirths.lock().unwrap().push(HookCall::Rebirth {
+                health: comp.health,
+                frame,
+            });
+        })
+        .on_rekill(move |frame, _entity| {
+            rekills.lock().unwrap().push(HookCall::Rekill { frame });
+        })
+        .on_record(|comp, _frame, entity| {
+            entity.insert(Synced(comp.health));
+        });
+
+    let mut app = setup_test_app();
+    app.register_rollback_with_hooks::<Enemy>(hooks);
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    (app, calls)
+}
+
+#[test]
+fn hooks_restore_and_record() {
+    let (mut app, calls) = setup_app_with_hooks();
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    for _ in 0..4 {
+        tick(&mut app); // frames 1..=4
+    }
+    assert_eq!(app.world.get::<Synced>(e1), Some(&Synced(6)));
+    assert!(calls.lock().unwrap().is_empty());
+
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(2, Enemy { health: 100 })
+        .unwrap();
+    tick(&mut app); // frame 5, rollback to 3
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![HookCall::Restore {
+            from: 6,
+            to: 100,
+            frame: 2
+        }]
+    );
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 97);
+    assert_eq!(app.world.get::<Synced>(e1), Some(&Synced(97)));
+}
+
+#[test]
+fn hooks_rebirth_and_rekill() {
+    let (mut app, calls) = setup_app_with_hooks();
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    for _ in 0..3 {
+        tick(&mut app); // frames 1..=3
+    }
+    app.world.entity_mut(e1).insert(DespawnMarker::for_frame(4));
+    tick(&mut app); // frame 4, Enemy removed
+    assert!(app.world.get::<Enemy>(e1).is_none());
+
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(2, Enemy { health: 100 })
+        .unwrap();
+    tick(&mut app); // frame 5, rollback to 3
+
+    // reinserted to resimulate from frame 2, then removed again on frame 4
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![
+            HookCall::Rebirth {
+                health: 100,
+                frame: 2
+            },
+            HookCall::Rekill { frame: 4 },
+        ]
+    );
+    assert!(app.world.get::<Enemy>(e1).is_none());
+}
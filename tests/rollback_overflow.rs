@@ -0,0 +1,83 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// runs until frame 15, then supplies a snapshot for frame 3, which is outside the window.
+fn run_scenario(policy: RollbackOverflowPolicy) -> (App, Entity) {
+    let mut app = setup_test_app();
+    app.world.resource_mut::<TimewarpConfig>().overflow_policy = policy;
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 100 }).id();
+    for _ in 0..15 {
+        tick(&mut app);
+    }
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 85);
+
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(3, Enemy { health: 1000 })
+        .unwrap();
+
+    tick(&mut app); // frame 16
+    (app, e1)
+}
+
+fn stats(app: &App) -> &RollbackStats {
+    app.world.resource::<RollbackStats>()
+}
+
+#[test]
+#[should_panic]
+fn overflow_panics_by_default() {
+    run_scenario(RollbackOverflowPolicy::Panic);
+}
+
+#[test]
+fn overflow_clamps_to_oldest_frame() {
+    let (app, e1) = run_scenario(RollbackOverflowPolicy::Clamp);
+    assert_eq!(stats(&app).rollback_overflows, 1);
+    assert_eq!(stats(&app).num_rollbacks, 1);
+    let rb = &app.world.resource::<PreviousRollback>().0;
+    assert_eq!(rb.range, (15 + 2 - TEST_ROLLBACK_WINDOW)..15);
+    // the snapshot was too old to store, so it was loaded at the oldest frame we could
+    // rollback to, and resimulated from there
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 990);
+}
+
+#[test]
+fn overflow_drops_snapshot() {
+    let (app, e1) = run_scenario(RollbackOverflowPolicy::Drop);
+    assert_eq!(stats(&app).rollback_overflows, 1);
+    assert_eq!(stats(&app).num_rollbacks, 0);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 84);
+    // only the EmitEvent policy sends events
+    assert!(app.world.resource::<Events<RollbackOverflow>>().is_empty());
+}
+
+#[test]
+fn overflow_snaps_to_snapshot() {
+    let (app, e1) = run_scenario(RollbackOverflowPolicy::Snap);
+    assert_eq!(stats(&app).rollback_overflows, 1);
+    assert_eq!(stats(&app).num_rollbacks, 0);
+    // snapped before frame 16 was simulated
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 999);
+}
+
+#[test]
+fn overflow_emits_event() {
+    let (app, e1) = run_scenario(RollbackOverflowPolicy::EmitEvent);
+    assert_eq!(stats(&app).rollback_overflows, 1);
+    assert_eq!(stats(&app).num_rollbacks, 0);
+    let events = app.world.resource::<Events<RollbackOverflow>>();
+    let mut reader = events.get_reader();
+    let ev = reader
+        .iter(events)
+        .next()
+        .expect("expected an overflow event");
+    assert_eq!(ev.range, 4..15);
+    assert_eq!(ev.entities, vec![e1]);
+}
@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// which systems ran, and on which frame
+#[derive(Resource, Default)]
+struct Ran(Vec<(&'static str, FrameNumber)>);
+
+impl Ran {
+    fn frames(&self, name: &str) -> Vec<FrameNumber> {
+        self.0
+            .iter()
+            .filter(|(n, _)| *n == name)
+            .map(|(_, f)| *f)
+            .collect()
+    }
+}
+
+fn record(name: &'static str) -> impl Fn(ResMut<Ran>, Res<GameClock>) {
+    move |mut ran: ResMut<Ran>, game_clock: Res<GameClock>| {
+        ran.0.push((name, game_clock.frame()));
+    }
+}
+
+#[test]
+fn run_conditions_during_rollback() {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.init_resource::<Ran>();
+    app.add_systems(
+        FixedUpdate,
+        (
+            take_damage.in_set(TimewarpGameSet::Simulation),
+            record("side_effect").in_set(TimewarpGameSet::SideEffect),
+            record("in_rollback").run_if(in_rollback()),
+            record("not_in_rollback").run_if(not_in_rollback()),
+            record("started").run_if(rollback_started()),
+            record("completed").run_if(rollback_just_completed()),
+            record("resim_4").run_if(resimulating_frame(4)),
+        )
+            .chain()
+            .in_set(TimewarpTestSets::GameLogic),
+    );
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    rollback_to_frame_3(&mut app, e1, Enemy { health: 100 });
+    tick(&mut app); // frame 7
+
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 95);
+    let ran = app.world.resource::<Ran>();
+    assert_eq!(ran.frames("side_effect"), vec![1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(ran.frames("not_in_rollback"), vec![1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(ran.frames("in_rollback"), vec![3, 4, 5]);
+    assert_eq!(ran.frames("started"), vec![3]);
+    assert_eq!(ran.frames("completed"), vec![6]);
+    assert_eq!(ran.frames("resim_4"), vec![4]);
+}
+
+#[test]
+fn game_sets_run_between_prefix_and_postfix() {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.init_resource::<Ran>();
+    // not in the configured game logic sets
+    app.add_systems(
+        FixedUpdate,
+        (
+            take_damage.in_set(TimewarpGameSet::Simulation),
+            record("side_effect").in_set(TimewarpGameSet::SideEffect),
+        ),
+    );
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    for _ in 0..3 {
+        tick(&mut app);
+    }
+    // recorded after the damage was done, on the frame it was done
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 1).unwrap().health, 9);
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 3).unwrap().health, 7);
+    assert_eq!(
+        app.world.resource::<Ran>().frames("side_effect"),
+        vec![1, 2, 3]
+    );
+}
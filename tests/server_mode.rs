@@ -0,0 +1,59 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// health of every enemy at frame 2, as read via TimewarpHistory
+#[derive(Resource, Default)]
+struct Rewound(Vec<(Entity, i32)>);
+
+fn read_history(history: TimewarpHistory<Enemy>, mut rewound: ResMut<Rewound>) {
+    rewound.0 = history
+        .iter_at_frame(2)
+        .map(|(entity, enemy)| (entity, enemy.health))
+        .collect();
+}
+
+fn setup_server_app() -> App {
+    let mut app = setup_test_app_with(test_config().with_mode(TimewarpMode::Server));
+    app.init_resource::<Rewound>();
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    app.add_systems(Update, read_history);
+    app
+}
+
+#[test]
+fn server_records_history_without_snapshots() {
+    let mut app = setup_server_app();
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    for _ in 0..4 {
+        tick(&mut app); // frames 1..=4
+    }
+
+    assert!(app.world.get::<ServerSnapshot<Enemy>>(e1).is_none());
+    assert!(app.world.get::<TimewarpStatus>(e1).is_none());
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 3).unwrap().health, 7);
+    assert_eq!(app.world.resource::<Rewound>().0, vec![(e1, 8)]);
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 0);
+}
+
+#[test]
+fn server_history_respects_lifecycle() {
+    let mut app = setup_server_app();
+    tick(&mut app); // frame 1
+    tick(&mut app); // frame 2
+
+    // spawned after frame 2, so has no value then
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    let e2 = app.world.spawn(Enemy { health: 10 }).id();
+    tick(&mut app); // frame 3
+    app.world.entity_mut(e2).insert(DespawnMarker::for_frame(4));
+    tick(&mut app); // frame 4
+
+    assert!(app.world.get::<Enemy>(e2).is_none());
+    assert!(app.world.resource::<Rewound>().0.is_empty());
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 3).unwrap().health, 9);
+    assert_eq!(app.comp_val_at::<Enemy>(e2, 3).unwrap().health, 9);
+}
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+fn setup_batch_app() -> (App, Entity, Entity) {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    let e2 = app.world.spawn(Enemy { health: 20 }).id();
+    for _ in 0..5 {
+        tick(&mut app);
+    }
+    // frame 5: e1 = 5, e2 = 15
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 5);
+    (app, e1, e2)
+}
+
+fn num_rollbacks(app: &App) -> u64 {
+    app.world.resource::<RollbackStats>().num_rollbacks
+}
+
+#[test]
+fn complete_batch_supersedes_older_partial() {
+    let (mut app, e1, e2) = setup_batch_app();
+
+    // an old partial batch that disagrees with our prediction..
+    SnapshotBatch::partial(2)
+        .with_component(e1, Enemy { health: 100 })
+        .apply_to_world(&mut app.world)
+        .unwrap();
+    // ..but a newer complete batch which agrees with it.
+    SnapshotBatch::complete(3)
+        .with_component(e1, Enemy { health: 7 })
+        .with_component(e2, Enemy { health: 17 })
+        .apply_to_world(&mut app.world)
+        .unwrap();
+
+    tick(&mut app); // frame 6
+
+    assert_eq!(num_rollbacks(&app), 0);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 4);
+}
+
+#[test]
+fn partial_batches_rollback_to_oldest() {
+    let (mut app, e1, e2) = setup_batch_app();
+
+    SnapshotBatch::partial(2)
+        .with_component(e1, Enemy { health: 100 })
+        .apply_to_world(&mut app.world)
+        .unwrap();
+    SnapshotBatch::partial(3)
+        .with_component(e2, Enemy { health: 200 })
+        .apply_to_world(&mut app.world)
+        .unwrap();
+
+    tick(&mut app); // frame 6, rollback to 3
+
+    assert_eq!(num_rollbacks(&app), 1);
+    assert_eq!(
+        app.world.resource::<PreviousRollback>().0.range.start,
+        3,
+        "should resimulate from the frame after the oldest partial batch"
+    );
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 3).unwrap().health, 99);
+    // the newer partial batch was applied when resimulation reached frame 3
+    assert_eq!(app.comp_val_at::<Enemy>(e2, 3).unwrap().health, 200);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 96);
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 197);
+}
+
+#[test]
+fn partial_batch_newer_than_complete() {
+    let (mut app, e1, e2) = setup_batch_app();
+
+    SnapshotBatch::partial(1)
+        .with_component(e2, Enemy { health: 1000 })
+        .apply_to_world(&mut app.world)
+        .unwrap();
+    SnapshotBatch::complete(2)
+        .with_component(e1, Enemy { health: 100 })
+        .with_component(e2, Enemy { health: 18 })
+        .apply_to_world(&mut app.world)
+        .unwrap();
+    SnapshotBatch::partial(3)
+        .with_component(e2, Enemy { health: 200 })
+        .apply_to_world(&mut app.world)
+        .unwrap();
+
+    tick(&mut app); // frame 6, rollback to 3
+
+    assert_eq!(num_rollbacks(&app), 1);
+    assert_eq!(app.world.resource::<PreviousRollback>().0.range.start, 3);
+    assert_eq!(app.comp_val_at::<Enemy>(e1, 3).unwrap().health, 99);
+    assert_eq!(app.comp_val_at::<Enemy>(e2, 3).unwrap().health, 200);
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 197);
+}
+
+#[test]
+fn batch_adds_timewarp_components() {
+    let (mut app, _e1, _e2) = setup_batch_app();
+
+    let e3 = app.world.spawn_empty().id();
+    SnapshotBatch::partial(3)
+        .with_component(e3, Enemy { health: 50 })
+        .apply_to_world(&mut app.world)
+        .unwrap();
+
+    tick(&mut app); // frame 6, rollback to 4
+
+    assert_eq!(num_rollbacks(&app), 1);
+    assert_eq!(app.comp_val_at::<Enemy>(e3, 3).unwrap().health, 50);
+    assert_eq!(app.world.get::<Enemy>(e3).unwrap().health, 47);
+}
+
+#[test]
+fn batch_with_missing_entity_is_not_applied() {
+    let (mut app, e1, _e2) = setup_batch_app();
+
+    let gone = app.world.spawn_empty().id();
+    app.world.despawn(gone);
+
+    let result = SnapshotBatch::complete(3)
+        .with_component(e1, Enemy { health: 100 })
+        .with_component(gone, Enemy { health: 100 })
+        .apply_to_world(&mut app.world);
+    assert!(matches!(result, Err(TimewarpError::EntityMissing(e)) if e == gone));
+    assert!(app
+        .world
+        .get::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .at_frame(3)
+        .is_none());
+
+    for _ in 0..TEST_ROLLBACK_WINDOW {
+        tick(&mut app);
+    }
+    let result = SnapshotBatch::complete(5)
+        .with_component(e1, Enemy { health: 100 })
+        .apply_to_world(&mut app.world);
+    assert!(matches!(result, Err(TimewarpError::FrameTooOld)));
+}
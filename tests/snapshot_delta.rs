@@ -0,0 +1,129 @@
+use bevy::{ecs::system::SystemState, prelude::*};
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+impl TimewarpDelta for Enemy {
+    type Delta = i32;
+    fn diff(&self, baseline: &Self) -> Option<i32> {
+        (self.health != baseline.health).then_some(self.health - baseline.health)
+    }
+    fn apply_delta(baseline: &Self, delta: &i32) -> Self {
+        Enemy {
+            health: baseline.health + delta,
+        }
+    }
+}
+
+/// doesn't take damage
+#[derive(Component)]
+struct Invulnerable;
+
+fn take_damage(mut q: Query<&mut Enemy, Without<Invulnerable>>) {
+    for mut enemy in q.iter_mut() {
+        enemy.health -= 1;
+    }
+}
+
+fn setup_app(mode: TimewarpMode) -> App {
+    let mut app = setup_test_app_with(test_config().with_mode(mode));
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    app
+}
+
+/// spawns the same entities in both apps, so the entity ids match.
+/// e3 is spawned after frame 2.
+fn run_both(server: &mut App, client: &mut App) -> (Entity, Entity, Entity) {
+    let spawn = |app: &mut App| {
+        let e1 = app.world.spawn(Enemy { health: 10 }).id();
+        let e2 = app.world.spawn((Enemy { health: 10 }, Invulnerable)).id();
+        (e1, e2)
+    };
+    let (e1, e2) = spawn(server);
+    assert_eq!((e1, e2), spawn(client));
+    for app in [&mut *server, &mut *client] {
+        tick(app); // frame 1
+        tick(app); // frame 2
+    }
+    let e3 = server.world.spawn(Enemy { health: 10 }).id();
+    assert_eq!(e3, client.world.spawn(Enemy { health: 10 }).id());
+    for app in [&mut *server, &mut *client] {
+        tick(app); // frame 3
+        tick(app); // frame 4
+    }
+    (e1, e2, e3)
+}
+
+#[test]
+fn delta_round_trip() {
+    let mut server = setup_app(TimewarpMode::Server);
+    let mut client = setup_app(TimewarpMode::Client);
+    let (e1, e2, e3) = run_both(&mut server, &mut client);
+
+    let mut state = SystemState::<TimewarpHistory<Enemy>>::new(&mut server.world);
+    let delta = state.get(&server.world).delta_since(2);
+    assert_eq!(delta.frame, 4);
+    let mut deltas = delta.deltas.clone();
+    deltas.sort_by_key(|(entity, _)| *entity);
+    assert_eq!(
+        deltas,
+        vec![
+            (e1, ComponentDelta::Changed(-2)),
+            (e2, ComponentDelta::Unchanged),
+            (e3, ComponentDelta::Full(Enemy { health: 8 })),
+        ]
+    );
+    assert_eq!(delta.num_changed(), 2);
+
+    // the client already has the server's values for the acknowledged frame
+    for (entity, health) in [(e1, 8), (e2, 10)] {
+        client
+            .world
+            .get_mut::<ServerSnapshot<Enemy>>(entity)
+            .unwrap()
+            .insert(2, Enemy { health })
+            .unwrap();
+    }
+    let mut batch = SnapshotBatch::partial(4);
+    delta.add_to_batch(&client.world, &mut batch).unwrap();
+    assert_eq!(batch.len(), 3);
+    batch.apply_to_world(&mut client.world).unwrap();
+
+    for (entity, health) in [(e1, 6), (e2, 10), (e3, 8)] {
+        let ss = client.world.get::<ServerSnapshot<Enemy>>(entity).unwrap();
+        assert_eq!(ss.at_frame(4), Some(&Enemy { health }));
+    }
+    tick(&mut client); // frame 5
+    assert_eq!(client.world.resource::<RollbackStats>().num_rollbacks, 0);
+}
+
+#[test]
+fn delta_without_baseline_fails() {
+    let mut server = setup_app(TimewarpMode::Server);
+    let mut client = setup_app(TimewarpMode::Client);
+    run_both(&mut server, &mut client);
+
+    let mut state = SystemState::<TimewarpHistory<Enemy>>::new(&mut server.world);
+    let delta = state.get(&server.world).delta_since(2);
+
+    // client never received frame 2
+    let mut batch = SnapshotBatch::partial(4);
+    let result = delta.add_to_batch(&client.world, &mut batch);
+    assert!(matches!(
+        result,
+        Err(TimewarpError::MissingBaseline { frame: 2, .. })
+    ));
+    assert!(batch.is_empty());
+
+    let mut batch = SnapshotBatch::partial(3);
+    let result = delta.add_to_batch(&client.world, &mut batch);
+    assert!(matches!(
+        result,
+        Err(TimewarpError::FrameMismatch {
+            expected: 3,
+            got: 4
+        })
+    ));
+}
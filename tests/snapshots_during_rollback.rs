@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+/// snapshots for frames inside the rollback range aren't overwritten by resimulation
+#[test]
+fn snapshot_inside_rollback_range_is_applied() {
+    let mut app = setup_test_app();
+    app.world
+        .resource_mut::<TimewarpConfig>()
+        .consolidation_strategy = RollbackConsolidationStrategy::Oldest;
+    app.register_rollback::<Enemy>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    let e2 = app.world.spawn(Enemy { health: 20 }).id();
+    for _ in 0..5 {
+        tick(&mut app); // frames 1..=5
+    }
+
+    // snapshots for different frames arrive together
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(2, Enemy { health: 100 })
+        .unwrap();
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e2)
+        .unwrap()
+        .insert(3, Enemy { health: 200 })
+        .unwrap();
+    tick(&mut app); // frame 6, rollback to 3
+
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert_eq!(app.world.resource::<PreviousRollback>().0.range.start, 3);
+    // resimulating frame 3 didn't replace the server's value for it
+    assert_eq!(app.comp_val_at::<Enemy>(e2, 3).unwrap().health, 200);
+    assert_eq!(app.world.get::<Enemy>(e2).unwrap().health, 197);
+    assert_eq!(app.world.get::<Enemy>(e1).unwrap().health, 96);
+}
@@ -4,11 +4,6 @@ use bevy_timewarp::prelude::*;
 mod test_utils;
 use test_utils::*;
 
-fn inc_frame(mut game_clock: ResMut<GameClock>, rb: Option<Res<Rollback>>) {
-    game_clock.advance(1);
-    info!("FRAME --> {:?} rollback:{rb:?}", game_clock.frame());
-}
-
 fn take_damage(mut q: Query<(Entity, &mut Enemy, &EntName)>) {
     for (entity, mut enemy, name) in q.iter_mut() {
         enemy.health -= 1;
@@ -45,7 +40,7 @@ fn spawning_in_the_past() {
 
     app.add_systems(
         FixedUpdate,
-        (inc_frame, take_damage, log_all)
+        (take_damage, log_all)
             .chain()
             .in_set(TimewarpTestSets::GameLogic),
     );
@@ -166,7 +161,7 @@ fn spawning_in_the_past_with_ss_partial_updates() {
 
     app.add_systems(
         FixedUpdate,
-        (inc_frame, take_damage, log_all)
+        (take_damage, log_all)
             .chain()
             .in_set(TimewarpTestSets::GameLogic),
     );
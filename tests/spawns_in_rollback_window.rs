@@ -4,11 +4,6 @@ use bevy_timewarp::prelude::*;
 mod test_utils;
 use test_utils::*;
 
-fn inc_frame(mut game_clock: ResMut<GameClock>, rb: Option<Res<Rollback>>) {
-    game_clock.advance(1);
-    info!("FRAME --> {:?} rollback:{rb:?}", game_clock.frame());
-}
-
 fn take_damage(mut q: Query<(Entity, &mut Enemy, &EntName)>) {
     for (entity, mut enemy, name) in q.iter_mut() {
         enemy.health -= 1;
@@ -32,7 +27,7 @@ fn rollback_over_new_spawn() {
     // runs when a rollback is NOT in progress.
     app.add_systems(
         FixedUpdate,
-        (inc_frame, take_damage, log_all)
+        (take_damage, log_all)
             .chain()
             .in_set(TimewarpTestSets::GameLogic)
             .run_if(not(resource_exists::<Rollback>())),
@@ -40,7 +35,7 @@ fn rollback_over_new_spawn() {
     // the core simulation-only game loop, for running during a rollback
     app.add_systems(
         FixedUpdate,
-        (inc_frame, take_damage, log_all)
+        (take_damage, log_all)
             .chain()
             .in_set(TimewarpTestSets::GameLogic)
             .run_if(resource_exists::<Rollback>()),
@@ -12,26 +12,56 @@ pub enum TimewarpTestSets {
     GameLogic, // game logic here
 }
 
+// the helpers below aren't used by every test crate that includes this module.
+
+#[allow(dead_code)]
 #[derive(Component, Default, Debug, Clone, PartialEq)]
 pub struct Enemy {
     pub health: i32,
 }
+#[allow(dead_code)]
 #[derive(Component, Default, Debug, Clone)]
 pub struct EntName {
     pub name: String,
 }
 
-pub fn setup_test_app() -> App {
-    let tw_config = TimewarpConfig::new(TimewarpTestSets::GameLogic, TimewarpTestSets::GameLogic)
+/// every Enemy takes 1 damage per frame
+#[allow(dead_code)]
+pub fn take_damage(mut q: Query<(Entity, &mut Enemy)>) {
+    for (entity, mut enemy) in q.iter_mut() {
+        enemy.health -= 1;
+        info!("{entity:?} took 1 damage -> {enemy:?}");
+    }
+}
+
+/// the config [`setup_test_app`] uses, for tests that need to tweak it first.
+#[allow(dead_code)]
+pub fn test_config() -> TimewarpConfig {
+    TimewarpConfig::new(TimewarpTestSets::GameLogic, TimewarpTestSets::GameLogic)
         .with_rollback_window(TEST_ROLLBACK_WINDOW)
-        .with_schedule(FixedUpdate);
+        .with_schedule(FixedUpdate)
+}
+
+#[allow(dead_code)]
+pub fn setup_test_app() -> App {
+    setup_test_app_with(test_config())
+}
+
+#[allow(dead_code)]
+pub fn setup_test_app_with(tw_config: TimewarpConfig) -> App {
+    setup_test_app_for::<GameClock>(tw_config)
+}
+
+/// like [`setup_test_app_with`], with timewarp reading and rewinding the `F` resource.
+#[allow(dead_code)]
+pub fn setup_test_app_for<F: FrameSource + FromWorld>(tw_config: TimewarpConfig) -> App {
     let mut app = App::new();
     app.add_plugins(bevy::log::LogPlugin {
         level: bevy::log::Level::TRACE,
         filter: "bevy_timewarp=trace".to_string(),
     });
-    app.add_plugins(TimewarpPlugin::new(tw_config));
-    app.add_plugins(bevy::time::TimePlugin::default());
+    app.add_plugins(TimewarpPlugin::<F>::with_frame_source(tw_config));
+    app.add_plugins(bevy::time::TimePlugin);
     app.insert_resource(FixedTime::new(TIMESTEP));
     warn!("⏱️Instant::now= {:?}", bevy::utils::Instant::now());
     app
@@ -44,11 +74,31 @@ pub fn tick(app: &mut App) {
     let period = fxt.period;
     fxt.tick(period);
     app.update();
-    let f = app.world.resource::<GameClock>().frame();
-    info!("end of update for {f} ----------------------------------------------------------");
+    // (no GameClock if the test uses its own FrameSource)
+    let f = app
+        .world
+        .get_resource::<GameClock>()
+        .map(|clock| clock.frame());
+    info!("end of update for {f:?} -------------------------------------------------------");
+}
+
+/// ticks frames 1..=5, then a snapshot of `value` for frame 2 makes frame 6 rollback to 3.
+#[allow(dead_code)]
+pub fn rollback_to_frame_3<T: TimewarpComponent>(app: &mut App, entity: Entity, value: T) {
+    for _ in 0..5 {
+        tick(app);
+    }
+    app.world
+        .get_mut::<ServerSnapshot<T>>(entity)
+        .unwrap()
+        .insert(2, value)
+        .unwrap();
+    tick(app); // frame 6, rollback to 3
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
 }
 
 // some syntactic sugar, just to make tests less of an eyesore:
+#[allow(dead_code)]
 pub(crate) trait TimewarpTestTraits {
     fn comp_val_at<T: TimewarpComponent>(&self, entity: Entity, frame: FrameNumber) -> Option<&T>;
 }
@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+use bevy_timewarp::prelude::*;
+use std::time::Duration;
+
+mod test_utils;
+use test_utils::*;
+
+fn config() -> TimewarpConfig {
+    TimewarpConfig::new(TimewarpTestSets::GameLogic, TimewarpTestSets::GameLogic)
+        .with_schedule(FixedUpdate)
+}
+
+#[test]
+fn tick_rate_applied_over_default_fixed_time() {
+    let mut app = App::new();
+    app.add_plugins(bevy::time::TimePlugin);
+    app.add_plugins(TimewarpPlugin::new(config().with_tick_rate(30.0)));
+    assert_eq!(
+        app.world.resource::<FixedTime>().period,
+        Duration::from_secs_f32(1.0 / 30.0)
+    );
+}
+
+#[test]
+fn configured_fixed_time_is_kept() {
+    let mut app = App::new();
+    app.insert_resource(FixedTime::new(TIMESTEP));
+    app.add_plugins(TimewarpPlugin::new(config()));
+    assert_eq!(app.world.resource::<FixedTime>().period, TIMESTEP);
+}
+
+#[test]
+fn fixed_time_defaults_to_60fps() {
+    let mut app = App::new();
+    app.add_plugins(TimewarpPlugin::new(config()));
+    assert_eq!(
+        app.world.resource::<FixedTime>().period,
+        Duration::from_secs_f32(1.0 / 60.0)
+    );
+}
+
+#[test]
+fn frame_advances_before_game_logic() {
+    let mut app = setup_test_app();
+    app.add_systems(
+        FixedUpdate,
+        (|game_clock: Res<GameClock>, mut seen: Local<FrameNumber>| {
+            assert_eq!(game_clock.frame(), *seen + 1);
+            *seen = game_clock.frame();
+        })
+        .in_set(TimewarpTestSets::GameLogic),
+    );
+    for _ in 0..3 {
+        tick(&mut app);
+    }
+    assert_eq!(app.world.resource::<GameClock>().frame(), 3);
+}
@@ -0,0 +1,113 @@
+use bevy::{ecs::event::ManualEventReader, prelude::*};
+use bevy_timewarp::prelude::*;
+
+mod test_utils;
+use test_utils::*;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hit(i32);
+
+/// takes damage every frame, and reports a hit whenever health is a multiple of 4
+fn take_damage(mut q: Query<&mut Enemy>, mut hits: TimewarpEventWriter<Hit>) {
+    for mut enemy in q.iter_mut() {
+        enemy.health -= 1;
+        if enemy.health % 4 == 0 {
+            hits.send(Hit(enemy.health));
+        }
+    }
+}
+
+fn setup_events_app() -> (App, Entity) {
+    let mut app = setup_test_app();
+    app.register_rollback::<Enemy>();
+    app.add_timewarp_event::<Hit>();
+    app.add_systems(FixedUpdate, take_damage.in_set(TimewarpTestSets::GameLogic));
+    let e1 = app.world.spawn(Enemy { health: 10 }).id();
+    (app, e1)
+}
+
+/// ticks, returning the updates a consumer would see.
+/// (bevy events only last a couple of updates, so we read after every tick)
+fn tick_and_read(
+    app: &mut App,
+    num_ticks: usize,
+    reader: &mut ManualEventReader<TimewarpEventUpdate<Hit>>,
+) -> Vec<TimewarpEventUpdate<Hit>> {
+    let mut updates = Vec::new();
+    for _ in 0..num_ticks {
+        tick(app);
+        let events = app.world.resource::<Events<TimewarpEventUpdate<Hit>>>();
+        updates.extend(reader.iter(events).cloned());
+    }
+    updates
+}
+
+#[test]
+fn resimulated_events_delivered_once() {
+    let (mut app, e1) = setup_events_app();
+    let mut reader = ManualEventReader::default();
+
+    // health 8 at frame 2
+    assert_eq!(
+        tick_and_read(&mut app, 5, &mut reader),
+        vec![TimewarpEventUpdate::Emitted {
+            frame: 2,
+            event: Hit(8)
+        }]
+    );
+
+    // turns out we had one more health, so we hit 8 on frame 3 instead
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(1, Enemy { health: 10 })
+        .unwrap();
+    // frame 6, rollback to 2
+    let updates = tick_and_read(&mut app, 1, &mut reader);
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    assert_eq!(
+        updates,
+        vec![
+            TimewarpEventUpdate::Cancelled {
+                frame: 2,
+                event: Hit(8)
+            },
+            TimewarpEventUpdate::Emitted {
+                frame: 3,
+                event: Hit(8)
+            },
+        ]
+    );
+    let tw_events = app.world.resource::<TimewarpEvents<Hit>>();
+    assert_eq!(tw_events.emitted_at(2), Some(&vec![]));
+    assert_eq!(tw_events.emitted_at(3), Some(&vec![Hit(8)]));
+}
+
+#[test]
+fn unchanged_events_not_redelivered() {
+    let (mut app, e1) = setup_events_app();
+    app.world
+        .resource_mut::<TimewarpConfig>()
+        .force_rollback_always = true;
+    let mut reader = ManualEventReader::default();
+
+    assert_eq!(tick_and_read(&mut app, 5, &mut reader).len(), 1);
+
+    // agrees with our prediction, but we rollback anyway
+    app.world
+        .get_mut::<ServerSnapshot<Enemy>>(e1)
+        .unwrap()
+        .insert(1, Enemy { health: 9 })
+        .unwrap();
+    // frame 6, rollback to 2
+    let updates = tick_and_read(&mut app, 1, &mut reader);
+    assert_eq!(app.world.resource::<RollbackStats>().num_rollbacks, 1);
+    // only the new frame's hit, nothing from the resimulated frames
+    assert_eq!(
+        updates,
+        vec![TimewarpEventUpdate::Emitted {
+            frame: 6,
+            event: Hit(4)
+        }]
+    );
+}